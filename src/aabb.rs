@@ -1,16 +1,17 @@
-use super::hit::HitRecord;
 use super::vec::{Point3, FloatT};
 use super::ray::Ray;
-use super::hit::{Hit};
 
 use std::mem;
 
+#[derive(Clone, Copy)]
+/// An axis-aligned bounding box.
 pub struct AABB {
     min: Point3, 
     max: Point3
 }
 
 impl AABB {
+    /// Creates a box spanning `minimum` to `maximum`.
     pub fn new(minimum: Point3, maximum: Point3) -> AABB {
         AABB {
             min: minimum,
@@ -18,11 +19,49 @@ impl AABB {
         }
     }
 
+    /// Corner with the smallest coordinates.
     pub fn min(&self) -> Point3 { self.min }
+    /// Corner with the largest coordinates.
     pub fn max(&self) -> Point3 { self.max }
-}
 
-impl Hit for AABB {
+    /// Smallest box containing both boxes.
+    pub fn surrounding_box(box0: AABB, box1: AABB) -> AABB {
+        let small = Point3::new(box0.min.x().min(box1.min.x()),
+                                box0.min.y().min(box1.min.y()),
+                                box0.min.z().min(box1.min.z()));
+        let big = Point3::new(box0.max.x().max(box1.max.x()),
+                              box0.max.y().max(box1.max.y()),
+                              box0.max.z().max(box1.max.z()));
+        AABB::new(small, big)
+    }
+
+    /// Smallest box containing `bbox` and `p`.
+    pub fn surrounding_point(bbox: AABB, p: Point3) -> AABB {
+        AABB::surrounding_box(bbox, AABB::new(p, p))
+    }
+
+    /// Union of a set of boxes, None if any of them is unbounded or there are none.
+    pub fn surrounding_boxes<I>(boxes: I) -> Option<AABB>
+        where I: IntoIterator<Item = Option<AABB>> {
+        let mut boxes = boxes.into_iter();
+        let mut bbox = boxes.next()??;
+
+        for b in boxes {
+            bbox = AABB::surrounding_box(bbox, b?);
+        }
+        Some(bbox)
+    }
+
+    /// Center of the box.
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Total area of the six faces.
+    pub fn surface_area(&self) -> FloatT {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
 
     // Unoptimized hit for AABB
     //fn hit(&self, r: &Ray, mut t_min: FloatT, mut t_max: FloatT) -> bool {
@@ -38,21 +77,22 @@ impl Hit for AABB {
     //    true
     //}
 
-    fn hit(&self, r: &Ray, mut t_min: FloatT, mut t_max: FloatT) -> (bool, Option<HitRecord>) { 
+    /// Slab test, true if `r` passes through the box within `(t_min, t_max)`.
+    pub fn hit(&self, r: &Ray, mut t_min: FloatT, mut t_max: FloatT) -> bool {
         for a in 0..3 {
-            let invD = 1.0 / r.direction()[a];
-            let mut t0 = (self.min()[a] - r.origin()[a]) * invD;
-            let mut t1 = (self.max()[a] - r.origin()[a]) * invD;
+            let inv_d = 1.0 / r.direction()[a];
+            let mut t0 = (self.min()[a] - r.origin()[a]) * inv_d;
+            let mut t1 = (self.max()[a] - r.origin()[a]) * inv_d;
             
-            if invD < 0.0 { mem::swap(&mut t0, &mut t1); }
+            if inv_d < 0.0 { mem::swap(&mut t0, &mut t1); }
             
             t_min = if t0 > t_min { t0 } else { t_min };
             t_max = if t1 < t_max { t1 } else { t_max };
             
             if t_max <= t_min {
-                return (false, None);
+                return false;
             }
         }
-        (true, None)
+        true
     }
-}
\ No newline at end of file
+}
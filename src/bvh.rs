@@ -1,18 +1,234 @@
-use super::vec::{Point3, Vec3, FloatT};
+use super::vec::FloatT;
 use super::ray::Ray;
-use super::hit::{Hit, HitRecord};
+use super::hit::{Hit, HitRecord, World};
 use super::aabb::AABB;
 
-use std::rc::Rc;
+// Number of buckets centroids are binned into when evaluating SAH splits
+const SAH_BUCKETS: usize = 12;
+// Cost of traversing a node relative to intersecting a single primitive
+const TRAVERSAL_COST: FloatT = 0.125;
+// Nodes with this many primitives or fewer may become leaves
+const MAX_LEAF_SIZE: usize = 4;
 
-pub struct BVHNode {
+type Primitive = (AABB, Box<dyn Hit>);
 
-    left: Rc<BVHNode>,
-    right: Rc<BVHNode>,
+/// An interior node of a [`BVHTree`].
+pub struct BVHNode {
+    left: Box<dyn Hit>,
+    right: Box<dyn Hit>,
     bbox: AABB
 }
 
+impl Hit for BVHNode {
+    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> (bool, Option<HitRecord>) {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return (false, None);
+        }
+
+        // Only look for hits in the right subtree closer than the left hit
+        let (_, left_rec) = self.left.hit(r, t_min, t_max);
+        let closest = left_rec.as_ref().map_or(t_max, |rec| rec.t);
+        let (_, right_rec) = self.right.hit(r, t_min, closest);
+
+        let rec = right_rec.or(left_rec);
+        (rec.is_some(), rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    fn hit_any(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> bool {
+        self.bbox.hit(r, t_min, t_max) && (self.left.hit_any(r, t_min, t_max) || self.right.hit_any(r, t_min, t_max))
+    }
+}
+
+/// A bounding volume hierarchy over a [`World`], split using the surface area heuristic.
 pub struct BVHTree
 {
-    
+    root: Box<dyn Hit>,
+    // Objects without a bounding box can't be placed in the tree and are tested separately
+    unbounded: World
+}
+
+impl BVHTree {
+    /// Builds the hierarchy, objects without a bounding box are kept out of the tree.
+    pub fn new(world: World) -> BVHTree {
+        let mut prims: Vec<Primitive> = Vec::new();
+        let mut unbounded = World::new();
+
+        for object in world {
+            match object.bounding_box() {
+                Some(bbox) => prims.push((bbox, object)),
+                None => unbounded.push(object)
+            }
+        }
+
+        BVHTree {
+            root: Self::build(prims),
+            unbounded
+        }
+    }
+
+    fn leaf(prims: Vec<Primitive>) -> Box<dyn Hit> {
+        let mut objects: World = prims.into_iter().map(|(_, object)| object).collect();
+        if objects.len() == 1 {
+            objects.pop().unwrap()
+        } else {
+            Box::new(objects)
+        }
+    }
+
+    fn build(prims: Vec<Primitive>) -> Box<dyn Hit> {
+        if prims.len() <= 1 {
+            return Self::leaf(prims);
+        }
+
+        let bbox = prims.iter().skip(1).fold(prims[0].0, |b, (pb, _)| AABB::surrounding_box(b, *pb));
+        let c0 = prims[0].0.centroid();
+        let centroids = prims.iter().skip(1).fold(AABB::new(c0, c0), |b, (pb, _)| {
+            AABB::surrounding_point(b, pb.centroid())
+        });
+
+        let bucket_of = |pb: &AABB, axis: usize| -> usize {
+            let lo = centroids.min()[axis];
+            let extent = centroids.max()[axis] - lo;
+            let b = ((pb.centroid()[axis] - lo) / extent * SAH_BUCKETS as FloatT) as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        // Find the cheapest bucket boundary over all three axes
+        let mut best: Option<(FloatT, usize, usize)> = None;
+        for axis in 0..3 {
+            if centroids.max()[axis] - centroids.min()[axis] <= 0.0 {
+                continue;
+            }
+
+            let mut counts = [0usize; SAH_BUCKETS];
+            let mut bounds: [Option<AABB>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+            for (pb, _) in &prims {
+                let b = bucket_of(pb, axis);
+                counts[b] += 1;
+                bounds[b] = Some(bounds[b].map_or(*pb, |bb| AABB::surrounding_box(bb, *pb)));
+            }
+
+            for split in 0..SAH_BUCKETS - 1 {
+                let (n0, a0) = Self::bucket_cost(&counts[..=split], &bounds[..=split]);
+                let (n1, a1) = Self::bucket_cost(&counts[split + 1..], &bounds[split + 1..]);
+                if n0 == 0 || n1 == 0 {
+                    continue;
+                }
+
+                let cost = TRAVERSAL_COST + (n0 as FloatT * a0 + n1 as FloatT * a1) / bbox.surface_area();
+                if best.is_none_or(|(c, _, _)| cost < c) {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+
+        let leaf_cost = prims.len() as FloatT;
+        let (left, right): (Vec<Primitive>, Vec<Primitive>) = match best {
+            Some((cost, _, _)) if prims.len() <= MAX_LEAF_SIZE && cost >= leaf_cost => {
+                return Self::leaf(prims);
+            }
+            Some((_, axis, split)) => {
+                prims.into_iter().partition(|(pb, _)| bucket_of(pb, axis) <= split)
+            }
+            None if prims.len() <= MAX_LEAF_SIZE => {
+                return Self::leaf(prims);
+            }
+            None => {
+                // Every centroid is coincident, so just split the list in half
+                let mut left = prims;
+                let right = left.split_off(left.len() / 2);
+                (left, right)
+            }
+        };
+
+        Box::new(BVHNode {
+            left: Self::build(left),
+            right: Self::build(right),
+            bbox
+        })
+    }
+
+    // Number of primitives and the surface area of their combined bounds
+    fn bucket_cost(counts: &[usize], bounds: &[Option<AABB>]) -> (usize, FloatT) {
+        let n = counts.iter().sum();
+        let bbox = bounds.iter().flatten().copied().reduce(AABB::surrounding_box);
+        (n, bbox.map_or(0.0, |b| b.surface_area()))
+    }
+}
+
+impl Hit for BVHTree {
+    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> (bool, Option<HitRecord>) {
+        let (_, tree_rec) = self.root.hit(r, t_min, t_max);
+        let closest = tree_rec.as_ref().map_or(t_max, |rec| rec.t);
+        let (_, unbounded_rec) = self.unbounded.hit(r, t_min, closest);
+
+        let rec = unbounded_rec.or(tree_rec);
+        (rec.is_some(), rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.root.bounding_box()
+    }
+
+    fn hit_any(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> bool {
+        self.root.hit_any(r, t_min, t_max) || self.unbounded.hit_any(r, t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::{Vec3, Point3, Color};
+    use crate::sphere::Sphere;
+    use crate::material::{BSDF, Lambertian};
+
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn random_vec(rng: &mut StdRng, lo: FloatT, hi: FloatT) -> Vec3 {
+        Vec3::new(rng.gen_range(lo..hi), rng.gen_range(lo..hi), rng.gen_range(lo..hi))
+    }
+
+    #[test]
+    fn closest_hit_matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (mut objects, mut world) = (World::new(), World::new());
+        // Each sphere gets its own material so a hit can be traced back to its sphere
+        for _ in 0..300 {
+            let center: Point3 = random_vec(&mut rng, -10.0, 10.0);
+            let radius = rng.gen_range(0.1..1.0);
+            let mat: Arc<dyn BSDF> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+            objects.push(Box::new(Sphere::new(center, radius, mat.clone())));
+            world.push(Box::new(Sphere::new(center, radius, mat)));
+        }
+        let bvh = BVHTree::new(objects);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = random_vec(&mut rng, -15.0, 15.0);
+            // Aim somewhere inside the cloud of spheres
+            let target = random_vec(&mut rng, -10.0, 10.0);
+            let r = Ray::new(origin, target - origin);
+
+            match (bvh.hit(&r, 0.001, FloatT::INFINITY).1, world.hit(&r, 0.001, FloatT::INFINITY).1) {
+                (None, None) => {}
+                (Some(a), Some(b)) => {
+                    assert_eq!(a.t, b.t);
+                    assert!(Arc::ptr_eq(&a.mat, &b.mat), "hit a different sphere at t {}", a.t);
+                    hits += 1;
+                }
+                (a, b) => panic!("BVH hit {} but the linear scan hit {}", a.is_some(), b.is_some())
+            }
+        }
+        // Enough rays have to hit something for the test to mean anything
+        assert!(hits > 1000, "only {} hits", hits);
+    }
 }
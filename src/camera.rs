@@ -1,52 +1,336 @@
 use super::vec::{Vec3, Point3, FloatT};
 use super::ray::Ray;
+use super::hit::Hit;
+use super::image::Image;
+use super::sampler::{self, Distribution1D};
 
-pub struct Camera {
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Shape of the lens opening, which is the shape out of focus highlights take.
+#[derive(Clone)]
+pub enum ApertureShape {
+    /// A round opening.
+    Circle,
+    /// A regular polygon formed by `blades` straight blades, turned `rotation` degrees.
+    Blades {
+        /// Number of blades, at least 3.
+        blades: u32,
+        /// Rotation of the first corner from the camera's right, in degrees.
+        rotation: FloatT
+    },
+    /// An image stretched over the square around the lens, open in proportion to its brightness.
+    Mask(Arc<ApertureMask>)
+}
+
+/// An image of the lens opening, sampled by brightness.
+pub struct ApertureMask {
+    // One distribution over each row's columns and one over the rows
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D
+}
+
+impl ApertureMask {
+    /// Creates a mask from `image`, whose top row is at the top of the lens.
+    pub fn new(image: &Image) -> ApertureMask {
+        let rows: Vec<Distribution1D> = (0..image.height()).map(|y| {
+            Distribution1D::new((0..image.width()).map(|x| image.get(x, y).luminance().max(0.0)).collect())
+        }).collect();
+        let marginal = Distribution1D::new(rows.iter().map(|r| r.integral).collect());
+
+        ApertureMask {
+            rows,
+            marginal
+        }
+    }
+
+    // Point in [-1, 1]² for `u` in [0, 1)², with +y up
+    fn sample(&self, u: (FloatT, FloatT)) -> Vec3 {
+        let (v, y, _) = self.marginal.sample(u.1);
+        let (u, _, _) = self.rows[y].sample(u.0);
+        Vec3::new(2.0 * u - 1.0, 1.0 - 2.0 * v, 0.0)
+    }
+
+    /// True if the mask lets no light through at all.
+    pub fn is_closed(&self) -> bool {
+        self.marginal.integral <= 0.0
+    }
+}
+
+impl ApertureShape {
+    // Point on the lens of radius 1 for `u` in [0, 1)²
+    fn sample(&self, u: (FloatT, FloatT)) -> Vec3 {
+        match self {
+            ApertureShape::Circle => sampler::sample_unit_disc(u),
+            ApertureShape::Blades { blades, rotation } => sampler::sample_polygon(u, *blades, rotation.to_radians()),
+            ApertureShape::Mask(mask) => mask.sample(u)
+        }
+    }
+}
+
+/// Maps positions on the image to rays leaving the camera.
+pub trait Camera : Send + Sync {
+    /// Ray through the image at `s` across and `t` up, both in `[0, 1]`, leaving the lens at the
+    /// point picked by `lens` in `[0, 1)²`. None where the image sees nothing, such as outside a
+    /// fisheye's circle.
+    fn get_ray(&self, s: FloatT, t: FloatT, lens: (FloatT, FloatT)) -> Option<Ray>;
+
+    /// Ray through the image at `s` across and `t` up from the center of the lens.
+    fn center_ray(&self, s: FloatT, t: FloatT) -> Option<Ray> {
+        self.get_ray(s, t, (0.5, 0.5))
+    }
+
+    /// Focuses on the first surface in `world` at `s` across and `t` up. Returns false and
+    /// keeps the focus if nothing is there or the camera has no lens to focus.
+    fn focus_on(&mut self, _world: &dyn Hit, _s: FloatT, _t: FloatT) -> bool {
+        false
+    }
+}
+
+// Right, up and backwards unit vectors of a camera at `lookfrom` facing `lookat`
+fn frame(lookfrom: Point3, lookat: Point3, vup: Vec3) -> (Vec3, Vec3, Vec3) {
+    let cw = (lookfrom - lookat).normalized();
+    let cu = vup.cross(cw).normalized();
+    let cv = cw.cross(cu);
+    (cu, cv, cw)
+}
+
+/// A perspective camera with a thin lens, focused on a plane facing it.
+pub struct PerspectiveCamera {
     origin: Point3,
-    lower_left_corner: Point3,
+    // Viewport one unit in front of the camera
+    lower_left_corner: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
     cu: Vec3,
     cv: Vec3,
-    lens_radius: FloatT
+    lens_radius: FloatT,
+    focus_dist: FloatT,
+    aperture_shape: ApertureShape
 }
 
-impl Camera {
-    pub fn new(lookfrom: Point3, 
-               lookat: Point3, 
+impl PerspectiveCamera {
+    /// Creates a camera at `lookfrom` facing `lookat` with `vup` pointing up.
+    ///
+    /// `vfov` is the vertical field of view in degrees and `aspect_ratio` is width over height.
+    /// Objects `focus_dist` away are in focus, and a wider `aperature` blurs the rest more.
+    pub fn new(lookfrom: Point3,
+               lookat: Point3,
                vup: Vec3,
-               vfov: FloatT, 
+               vfov: FloatT,
                aspect_ratio: FloatT,
                aperature: FloatT,
-               focus_dist: FloatT) -> Camera {
+               focus_dist: FloatT) -> PerspectiveCamera {
 
         // Vertical FOV in degrees
         let theta = (std::f64::consts::PI as FloatT) / 180.0 * vfov;
         let viewport_height = 2.0 * (theta / 2.0).tan();
         let viewport_width = aspect_ratio * viewport_height;
 
-        let cw = (lookfrom - lookat).normalized();
-        let cu = vup.cross(cw).normalized();
-        let cv = cw.cross(cu);
-        let h = focus_dist * viewport_width * cu;
-        let v = focus_dist * viewport_height * cv;
-    
-        let llc = lookfrom - h / 2.0 - v / 2.0 - focus_dist * cw;
+        let (cu, cv, cw) = frame(lookfrom, lookat, vup);
+        let h = viewport_width * cu;
+        let v = viewport_height * cv;
+
+        let llc = -1.0 * (h / 2.0 + v / 2.0 + cw);
 
-        Camera {
+        PerspectiveCamera {
             origin: lookfrom,
             horizontal: h,
             vertical: v,
             lower_left_corner: llc,
-            cu: cu,
-            cv: cv,
-            lens_radius: aperature / 2.0
+            cu,
+            cv,
+            lens_radius: aperature / 2.0,
+            focus_dist,
+            aperture_shape: ApertureShape::Circle
+        }
+    }
+
+    /// Changes the shape of the lens opening, which is round by default.
+    pub fn set_aperture_shape(&mut self, shape: ApertureShape) {
+        self.aperture_shape = shape;
+    }
+
+    /// Distance to the plane in focus.
+    pub fn focus_dist(&self) -> FloatT {
+        self.focus_dist
+    }
+
+    // Ray from the center of the lens, its direction one unit deep
+    fn pinhole_ray(&self, s: FloatT, t: FloatT) -> Ray {
+        Ray::new(self.origin, self.lower_left_corner + s * self.horizontal + t * self.vertical)
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: FloatT, t: FloatT, lens: (FloatT, FloatT)) -> Option<Ray> {
+        if self.lens_radius <= 0.0 {
+            return Some(self.pinhole_ray(s, t));
+        }
+
+        let focus = self.focus_dist * self.pinhole_ray(s, t).direction();
+        let rd = self.lens_radius * self.aperture_shape.sample(lens);
+        let offset = rd.x() * self.cu + rd.y() * self.cv;
+        Some(Ray::new(self.origin + offset, focus - offset))
+    }
+
+    fn center_ray(&self, s: FloatT, t: FloatT) -> Option<Ray> {
+        Some(self.pinhole_ray(s, t))
+    }
+
+    fn focus_on(&mut self, world: &dyn Hit, s: FloatT, t: FloatT) -> bool {
+        // The direction is one unit deep, so the distance along it is the depth
+        match world.hit(&self.pinhole_ray(s, t), 0.001, FloatT::INFINITY).1 {
+            Some(rec) => {
+                self.focus_dist = rec.t;
+                true
+            }
+            None => false
+        }
+    }
+}
+
+/// A camera whose rays are all parallel, so sizes don't shrink with distance.
+pub struct OrthographicCamera {
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3
+}
+
+impl OrthographicCamera {
+    /// Creates a camera centered on `lookfrom` facing `lookat` with `vup` pointing up, seeing a
+    /// view `size` units tall and `aspect_ratio` times as wide.
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, size: FloatT, aspect_ratio: FloatT) -> OrthographicCamera {
+        let (cu, cv, cw) = frame(lookfrom, lookat, vup);
+        let h = size * aspect_ratio * cu;
+        let v = size * cv;
+
+        OrthographicCamera {
+            lower_left_corner: lookfrom - h / 2.0 - v / 2.0,
+            horizontal: h,
+            vertical: v,
+            direction: -1.0 * cw
         }
     }
+}
 
-    pub fn get_ray(&self, s: FloatT, t: FloatT) -> Ray {
-        Ray::new(self.origin,
-                 self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin)
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: FloatT, t: FloatT, _lens: (FloatT, FloatT)) -> Option<Ray> {
+        Some(Ray::new(self.lower_left_corner + s * self.horizontal + t * self.vertical, self.direction))
     }
+}
+
+/// An equidistant fisheye, where the distance from the center of the image is proportional to
+/// the angle from the view direction. The image circle fits the shorter side of the image.
+pub struct FisheyeCamera {
+    origin: Point3,
+    cu: Vec3,
+    cv: Vec3,
+    cw: Vec3,
+    half_fov: FloatT,
+    // Extent of the image along each axis in units of the image circle's radius
+    extent: (FloatT, FloatT)
+}
+
+impl FisheyeCamera {
+    /// Creates a fisheye at `lookfrom` facing `lookat` with `vup` pointing up, seeing `fov`
+    /// degrees across its image circle.
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, fov: FloatT, aspect_ratio: FloatT) -> FisheyeCamera {
+        let (cu, cv, cw) = frame(lookfrom, lookat, vup);
+        let shorter = aspect_ratio.min(1.0);
+
+        FisheyeCamera {
+            origin: lookfrom,
+            cu,
+            cv,
+            cw,
+            half_fov: fov.to_radians() / 2.0,
+            extent: (aspect_ratio / shorter, 1.0 / shorter)
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: FloatT, t: FloatT, _lens: (FloatT, FloatT)) -> Option<Ray> {
+        let (x, y) = ((2.0 * s - 1.0) * self.extent.0, (2.0 * t - 1.0) * self.extent.1);
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * self.half_fov;
+        let phi = y.atan2(x);
+        let dir = theta.sin() * (phi.cos() * self.cu + phi.sin() * self.cv) - theta.cos() * self.cw;
+        Some(Ray::new(self.origin, dir))
+    }
+}
 
-}
\ No newline at end of file
+/// A 360° panorama where the image's width spans every direction around and its height spans
+/// straight down to straight up, with the view direction in the middle.
+pub struct EquirectangularCamera {
+    origin: Point3,
+    cu: Vec3,
+    cv: Vec3,
+    cw: Vec3
+}
+
+impl EquirectangularCamera {
+    /// Creates a panorama from `lookfrom` centered on `lookat` with `vup` pointing up.
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3) -> EquirectangularCamera {
+        let (cu, cv, cw) = frame(lookfrom, lookat, vup);
+        EquirectangularCamera {
+            origin: lookfrom,
+            cu,
+            cv,
+            cw
+        }
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: FloatT, t: FloatT, _lens: (FloatT, FloatT)) -> Option<Ray> {
+        let phi = (2.0 * s - 1.0) * PI;
+        let lat = (t - 0.5) * PI;
+        let dir = lat.cos() * (phi.sin() * self.cu - phi.cos() * self.cw) + lat.sin() * self.cv;
+        Some(Ray::new(self.origin, dir))
+    }
+}
+
+/// Six 90° views side by side, looking right, left, up, down, forward and back, that together
+/// cover every direction. Each face is square when the image is six times as wide as it is tall.
+pub struct CubemapCamera {
+    origin: Point3,
+    // Forward, right and up of each face
+    faces: [(Vec3, Vec3, Vec3); 6]
+}
+
+impl CubemapCamera {
+    /// Creates a cubemap from `lookfrom` whose forward face looks at `lookat` with `vup` pointing up.
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3) -> CubemapCamera {
+        let (cu, cv, cw) = frame(lookfrom, lookat, vup);
+        let cf = -1.0 * cw;
+
+        CubemapCamera {
+            origin: lookfrom,
+            faces: [
+                (cu, cw, cv),
+                (-1.0 * cu, cf, cv),
+                (cv, cu, cw),
+                (-1.0 * cv, cu, cf),
+                (cf, cu, cv),
+                (cw, -1.0 * cu, cv)
+            ]
+        }
+    }
+}
+
+impl Camera for CubemapCamera {
+    fn get_ray(&self, s: FloatT, t: FloatT, _lens: (FloatT, FloatT)) -> Option<Ray> {
+        let x = s * 6.0;
+        let face = (x as usize).min(5);
+        let (forward, right, up) = self.faces[face];
+        let (a, b) = (2.0 * (x - face as FloatT) - 1.0, 2.0 * t - 1.0);
+        Some(Ray::new(self.origin, forward + a * right + b * up))
+    }
+}
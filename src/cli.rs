@@ -0,0 +1,315 @@
+use raytracing::output::{self, OutputOptions, EXRCompression, EXRPixelType};
+use raytracing::render::{AuxChannel, AdaptiveSampling};
+use raytracing::vec::FloatT;
+use raytracing::tile::TileOrder;
+use raytracing::sampler::SamplerType;
+use raytracing::filter::{Filter, FilterKind};
+use raytracing::tonemap::ToneMapOperator;
+use raytracing::light::MISHeuristic;
+
+use std::fmt;
+use std::path::Path;
+
+pub const USAGE: &str = "\
+Usage: raytracing [OPTIONS]
+
+Options:
+  --scene <FILE|NAME>  Scene file to render, or the name of one in ./scenes [default: debug]
+  --output <FILE>      Output image path, the format is picked from the extension:
+                       .ppm (binary), .pnm (plain text PPM), .pfm, .bmp, .png or .exr [default: image.ppm]
+  --bit-depth <8|16>   Bits per channel for PNG output [default: 8]
+  --tonemap <OPERATOR> Tone curve for PPM, BMP and PNG output: clamp, reinhard, reinhard-extended,
+                       aces or hable [default: clamp]
+  --white <LUMINANCE>  Luminance shown as pure white by reinhard-extended [default: 4]
+  --exposure <STOPS>   Brightens or darkens PPM, BMP and PNG output, may be negative [default: 0]
+  --exr-compression <none|zip|piz>
+                       Compression for EXR output [default: zip]
+  --exr-type <half|float>
+                       Storage type of EXR color channels [default: half]
+  --channels <LIST>    Comma separated extra channels to render into EXR output: depth, normal, samples
+  --width <PIXELS>     Image width, overrides the scene
+  --height <PIXELS>    Image height, overrides the scene (keeps the scene's aspect ratio if omitted)
+  --spp <N>            Samples per pixel, overrides the scene
+  --adaptive <ERROR>   Stop sampling a pixel once the standard error of its luminance drops below
+                       this fraction of the mean, e.g. 0.01. --spp becomes the maximum
+  --min-spp <N>        Samples per pixel before adaptive sampling may stop [default: 16]
+  --heatmap <FILE>     Also write an image of the number of samples taken per pixel
+  --filter <TYPE>      Pixel reconstruction filter: box, triangle, gaussian, mitchell or lanczos [default: box]
+  --filter-radius <R>  Filter radius in pixels [default: 0.5 box, 1 triangle, 1.5 gaussian, 2 mitchell, 3 lanczos]
+  --sampler <TYPE>     Sample sequence: independent, stratified, halton, sobol or bluenoise [default: sobol]
+  --depth <N>          Maximum ray bounce depth, overrides the scene
+  --diffuse-depth <N>  Maximum number of diffuse bounces [default: unlimited]
+  --specular-depth <N> Maximum number of specular reflections [default: unlimited]
+  --transmission-depth <N>
+                       Maximum number of refractions [default: unlimited]
+  --rr-depth <N>       Bounces before Russian roulette may end a path [default: 3]
+  --mis <HEURISTIC>    Weighting of light and BSDF samples: balance or power, or light or bsdf to use
+                       only one of them [default: power]
+  --threads <N>        Number of render threads [default: all cores]
+  --tile-size <PIXELS> Size of the square tiles handed to render threads [default: 32]
+  --tile-order <ORDER> Order tiles are rendered in: scanline, spiral or hilbert [default: spiral]
+  --seed <N>           Seed for the random number generator, equal seeds give identical images [default: 0]
+  -h, --help           Print this help";
+
+const FLAGS: [&str; 28] = ["--scene", "--output", "--width", "--height", "--spp", "--depth", "--threads", "--seed",
+                           "--bit-depth", "--exr-compression", "--exr-type", "--channels", "--tonemap", "--white",
+                           "--exposure", "--tile-size", "--tile-order", "--sampler", "--adaptive", "--min-spp",
+                           "--heatmap", "--filter", "--filter-radius", "--diffuse-depth", "--specular-depth",
+                           "--transmission-depth", "--rr-depth", "--mis"];
+
+const DEFAULT_WHITE: FloatT = 4.0;
+const DEFAULT_MIN_SPP: u64 = 16;
+const GAUSSIAN_SIGMA: FloatT = 0.5;
+const MITCHELL_B: FloatT = 1.0 / 3.0;
+const MITCHELL_C: FloatT = 1.0 / 3.0;
+
+pub struct Options {
+    pub scene: String,
+    pub output: String,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub samples_per_pixel: Option<u64>,
+    pub sampler: Option<SamplerType>,
+    pub filter: Filter,
+    pub adaptive: Option<AdaptiveSampling>,
+    pub heatmap: Option<String>,
+    pub max_depth: Option<u64>,
+    pub diffuse_depth: Option<u64>,
+    pub specular_depth: Option<u64>,
+    pub transmission_depth: Option<u64>,
+    pub roulette_depth: Option<u64>,
+    pub mis: Option<MISHeuristic>,
+    pub threads: Option<usize>,
+    pub tile_size: Option<usize>,
+    pub tile_order: Option<TileOrder>,
+    pub seed: Option<u64>,
+    pub aux_channels: Vec<AuxChannel>,
+    pub output_options: OutputOptions
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Help,
+    Invalid(String)
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::Invalid(msg) => write!(f, "{}", msg)
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+fn invalid<T>(msg: String) -> Result<T, CliError> {
+    Err(CliError::Invalid(msg))
+}
+
+fn positive<T>(flag: &str, value: &str) -> Result<T, CliError>
+    where T: std::str::FromStr + PartialOrd + Default {
+    match value.parse::<T>() {
+        Ok(v) if v > T::default() => Ok(v),
+        _ => invalid(format!("{} expects a positive integer, found '{}'", flag, value))
+    }
+}
+
+fn non_negative(flag: &str, value: &str) -> Result<u64, CliError> {
+    value.parse().or_else(|_| invalid(format!("{} expects a non-negative integer, found '{}'", flag, value)))
+}
+
+impl Options {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, CliError> {
+        let mut opts = Options {
+            scene: "debug".to_string(),
+            output: "image.ppm".to_string(),
+            width: None,
+            height: None,
+            samples_per_pixel: None,
+            sampler: None,
+            filter: Filter::default(),
+            adaptive: None,
+            heatmap: None,
+            max_depth: None,
+            diffuse_depth: None,
+            specular_depth: None,
+            transmission_depth: None,
+            roulette_depth: None,
+            mis: None,
+            threads: None,
+            tile_size: None,
+            tile_order: None,
+            seed: None,
+            aux_channels: Vec::new(),
+            output_options: OutputOptions::default()
+        };
+
+        let mut tonemap = "clamp".to_string();
+        let mut white = DEFAULT_WHITE;
+        let mut threshold = None;
+        let mut min_samples = DEFAULT_MIN_SPP;
+        let mut filter = "box".to_string();
+        let mut filter_radius = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Err(CliError::Help);
+            }
+            if !arg.starts_with("--") {
+                return invalid(format!("unexpected argument '{}'", arg));
+            }
+
+            // Accept both `--flag value` and `--flag=value`
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None)
+            };
+            if !FLAGS.contains(&flag.as_str()) {
+                return invalid(format!("unknown option '{}'", flag));
+            }
+            let Some(value) = value.or_else(|| args.next()) else {
+                return invalid(format!("{} expects a value", flag));
+            };
+
+            match flag.as_str() {
+                "--scene" => opts.scene = value,
+                "--output" => opts.output = value,
+                "--width" => opts.width = Some(positive(&flag, &value)?),
+                "--height" => opts.height = Some(positive(&flag, &value)?),
+                "--spp" => opts.samples_per_pixel = Some(positive(&flag, &value)?),
+                "--adaptive" => match value.parse::<FloatT>() {
+                    Ok(t) if t > 0.0 && t.is_finite() => threshold = Some(t),
+                    _ => return invalid(format!("--adaptive expects a positive number, found '{}'", value))
+                },
+                "--min-spp" => min_samples = positive(&flag, &value)?,
+                "--heatmap" => opts.heatmap = Some(value),
+                "--filter" => filter = value,
+                "--filter-radius" => match value.parse::<FloatT>() {
+                    Ok(r) if r > 0.0 && r.is_finite() => filter_radius = Some(r),
+                    _ => return invalid(format!("--filter-radius expects a positive number, found '{}'", value))
+                },
+                "--sampler" => match value.as_str() {
+                    "independent" => opts.sampler = Some(SamplerType::Independent),
+                    "stratified" => opts.sampler = Some(SamplerType::Stratified),
+                    "halton" => opts.sampler = Some(SamplerType::Halton),
+                    "sobol" => opts.sampler = Some(SamplerType::Sobol),
+                    "bluenoise" => opts.sampler = Some(SamplerType::BlueNoise),
+                    _ => return invalid(format!("--sampler expects independent, stratified, halton, sobol or bluenoise, found '{}'",
+                                                value))
+                },
+                "--depth" => opts.max_depth = Some(positive(&flag, &value)?),
+                "--diffuse-depth" => opts.diffuse_depth = Some(non_negative(&flag, &value)?),
+                "--specular-depth" => opts.specular_depth = Some(non_negative(&flag, &value)?),
+                "--transmission-depth" => opts.transmission_depth = Some(non_negative(&flag, &value)?),
+                "--rr-depth" => opts.roulette_depth = Some(non_negative(&flag, &value)?),
+                "--mis" => match value.as_str() {
+                    "balance" => opts.mis = Some(MISHeuristic::Balance),
+                    "power" => opts.mis = Some(MISHeuristic::Power),
+                    "light" => opts.mis = Some(MISHeuristic::LightOnly),
+                    "bsdf" => opts.mis = Some(MISHeuristic::BSDFOnly),
+                    _ => return invalid(format!("--mis expects balance, power, light or bsdf, found '{}'", value))
+                },
+                "--threads" => opts.threads = Some(positive(&flag, &value)?),
+                "--tile-size" => opts.tile_size = Some(positive(&flag, &value)?),
+                "--tile-order" => match value.as_str() {
+                    "scanline" => opts.tile_order = Some(TileOrder::Scanline),
+                    "spiral" => opts.tile_order = Some(TileOrder::Spiral),
+                    "hilbert" => opts.tile_order = Some(TileOrder::Hilbert),
+                    _ => return invalid(format!("--tile-order expects scanline, spiral or hilbert, found '{}'", value))
+                },
+                "--seed" => match value.parse::<u64>() {
+                    Ok(seed) => opts.seed = Some(seed),
+                    Err(_) => return invalid(format!("--seed expects a non-negative integer, found '{}'", value))
+                },
+                "--bit-depth" => match value.as_str() {
+                    "8" => opts.output_options.bit_depth = 8,
+                    "16" => opts.output_options.bit_depth = 16,
+                    _ => return invalid(format!("--bit-depth expects 8 or 16, found '{}'", value))
+                },
+                "--tonemap" => tonemap = value,
+                "--white" => match value.parse::<FloatT>() {
+                    Ok(w) if w > 0.0 && w.is_finite() => white = w,
+                    _ => return invalid(format!("--white expects a positive number, found '{}'", value))
+                },
+                "--exposure" => match value.parse::<FloatT>() {
+                    Ok(ev) if ev.is_finite() => opts.output_options.tone_map.exposure = ev,
+                    _ => return invalid(format!("--exposure expects a number, found '{}'", value))
+                },
+                "--exr-compression" => match value.as_str() {
+                    "none" => opts.output_options.exr_compression = EXRCompression::None,
+                    "zip" => opts.output_options.exr_compression = EXRCompression::ZIP,
+                    "piz" => opts.output_options.exr_compression = EXRCompression::PIZ,
+                    _ => return invalid(format!("--exr-compression expects none, zip or piz, found '{}'", value))
+                },
+                "--exr-type" => match value.as_str() {
+                    "half" => opts.output_options.exr_pixel_type = EXRPixelType::Half,
+                    "float" => opts.output_options.exr_pixel_type = EXRPixelType::Float,
+                    _ => return invalid(format!("--exr-type expects half or float, found '{}'", value))
+                },
+                "--channels" => for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                    let channel = match name {
+                        "depth" => AuxChannel::Depth,
+                        "normal" => AuxChannel::Normal,
+                        "samples" => AuxChannel::SampleCount,
+                        _ => return invalid(format!("unknown channel '{}', expected depth, normal or samples", name))
+                    };
+                    if !opts.aux_channels.contains(&channel) {
+                        opts.aux_channels.push(channel);
+                    }
+                },
+                _ => unreachable!()
+            }
+        }
+
+        let kind = match filter.as_str() {
+            "box" => FilterKind::Box,
+            "triangle" => FilterKind::Triangle,
+            "gaussian" => FilterKind::Gaussian { sigma: GAUSSIAN_SIGMA },
+            "mitchell" => FilterKind::Mitchell { b: MITCHELL_B, c: MITCHELL_C },
+            "lanczos" => FilterKind::Lanczos,
+            _ => return invalid(format!("unknown filter '{}', expected box, triangle, gaussian, mitchell or lanczos", filter))
+        };
+        opts.filter = match filter_radius {
+            Some(radius) => Filter::with_radius(kind, radius),
+            None => Filter::new(kind)
+        };
+        opts.adaptive = threshold.map(|threshold| AdaptiveSampling { threshold, min_samples });
+        opts.output_options.tone_map.operator = match tonemap.as_str() {
+            "clamp" => ToneMapOperator::Clamp,
+            "reinhard" => ToneMapOperator::Reinhard,
+            "reinhard-extended" => ToneMapOperator::ExtendedReinhard { white },
+            "aces" => ToneMapOperator::ACES,
+            "hable" | "uncharted2" => ToneMapOperator::Uncharted2,
+            _ => return invalid(format!("unknown tone map '{}', expected clamp, reinhard, reinhard-extended, aces or hable",
+                                        tonemap))
+        };
+
+        if opts.scene.is_empty() || opts.output.is_empty() {
+            return invalid("--scene and --output can't be empty".to_string());
+        }
+        for path in std::iter::once(&opts.output).chain(&opts.heatmap) {
+            if output::writer_for_path(Path::new(path), &opts.output_options).is_none() {
+                return invalid(format!("unsupported output format '{}'", path));
+            }
+        }
+        // Only EXR files have room for the extra channels
+        let exr = Path::new(&opts.output).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+        if !opts.aux_channels.is_empty() && !exr {
+            return invalid("--channels requires an .exr output".to_string());
+        }
+        Ok(opts)
+    }
+
+    // A bare scene name like `random` refers to ./scenes/random.scene
+    pub fn scene_path(&self) -> String {
+        let named = format!("./scenes/{}.scene", self.scene);
+        if !Path::new(&self.scene).exists() && Path::new(&named).exists() {
+            named
+        } else {
+            self.scene.clone()
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use super::vec::{Vec3, Color, FloatT};
+use super::image::Image;
+use super::sampler::Distribution1D;
+
+use std::f64::consts::PI;
+
+/// Light arriving from infinitely far away along every direction that leaves the scene.
+pub trait Environment : Send + Sync {
+    /// Radiance arriving from the unit direction `dir`.
+    fn radiance(&self, dir: Vec3) -> Color;
+
+    /// Picks a direction to sample light from for `u` in `[0, 1)²`, with its density per steradian.
+    /// None if the environment isn't sampled directly, leaving it to BSDF sampling.
+    fn sample(&self, _u: (FloatT, FloatT)) -> Option<(Vec3, FloatT)> {
+        None
+    }
+
+    /// Density with which [`Environment::sample`] picks `dir`.
+    fn pdf(&self, _dir: Vec3) -> FloatT {
+        0.0
+    }
+}
+
+/// Color of the default sky straight down.
+pub const DEFAULT_SKY_BOTTOM: Color = Color::new(1.0, 1.0, 1.0);
+/// Color of the default sky straight up.
+pub const DEFAULT_SKY_TOP: Color = Color::new(0.5, 0.7, 1.0);
+
+/// A sky that blends from one color straight down to another straight up.
+pub struct Gradient {
+    bottom: Color,
+    top: Color,
+    intensity: FloatT
+}
+
+impl Gradient {
+    /// Creates a gradient from `bottom` to `top`, both scaled by `intensity`.
+    pub fn new(bottom: Color, top: Color, intensity: FloatT) -> Gradient {
+        Gradient {
+            bottom,
+            top,
+            intensity
+        }
+    }
+}
+
+impl Default for Gradient {
+    /// White below fading to light blue above.
+    fn default() -> Gradient {
+        Gradient::new(DEFAULT_SKY_BOTTOM, DEFAULT_SKY_TOP, 1.0)
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, dir: Vec3) -> Color {
+        let t = 0.5 * (dir.y() + 1.0);
+        ((1.0 - t) * self.bottom + t * self.top) * self.intensity
+    }
+}
+
+/// An equirectangular map of the surroundings: the image's width wraps around the horizon and its
+/// height spans straight up to straight down. Directions are importance sampled by brightness.
+pub struct EnvironmentMap {
+    image: Image,
+    rotation: FloatT,
+    intensity: FloatT,
+    // One distribution over each row's columns and one over the rows
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D
+}
+
+impl EnvironmentMap {
+    /// Creates an environment from an equirectangular `image`, turned `rotation` degrees about the
+    /// vertical axis and scaled by `intensity`.
+    pub fn new(image: Image, rotation: FloatT, intensity: FloatT) -> EnvironmentMap {
+        let (width, height) = (image.width(), image.height());
+        // Rows near the poles cover less of the sphere
+        let rows: Vec<Distribution1D> = (0..height).map(|y| {
+            let sin_theta = (PI * (y as FloatT + 0.5) / height as FloatT).sin();
+            Distribution1D::new((0..width).map(|x| image.get(x, y).luminance().max(0.0) * sin_theta).collect())
+        }).collect();
+        let marginal = Distribution1D::new(rows.iter().map(|r| r.integral).collect());
+
+        EnvironmentMap {
+            image,
+            rotation: rotation.to_radians(),
+            intensity,
+            rows,
+            marginal
+        }
+    }
+
+    // Position of `dir` on the map, both coordinates in [0, 1)
+    fn to_uv(&self, dir: Vec3) -> (FloatT, FloatT) {
+        let theta = dir.y().clamp(-1.0, 1.0).acos();
+        let phi = (dir.z().atan2(dir.x()) + self.rotation).rem_euclid(2.0 * PI);
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn texel(&self, u: FloatT, v: FloatT) -> (usize, usize) {
+        let x = ((u * self.image.width() as FloatT) as usize).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as FloatT) as usize).min(self.image.height() - 1);
+        (x, y)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, dir: Vec3) -> Color {
+        let (u, v) = self.to_uv(dir);
+        let (x, y) = self.texel(u, v);
+        self.image.get(x, y) * self.intensity
+    }
+
+    fn sample(&self, u: (FloatT, FloatT)) -> Option<(Vec3, FloatT)> {
+        let (v, y, pdf_v) = self.marginal.sample(u.1);
+        let (u, _, pdf_u) = self.rows[y].sample(u.0);
+
+        let theta = PI * v;
+        let phi = 2.0 * PI * u - self.rotation;
+        let sin_theta = theta.sin();
+        if sin_theta <= 0.0 {
+            return None;
+        }
+        let dir = Vec3::new(sin_theta * phi.cos(), theta.cos(), sin_theta * phi.sin());
+        // Converting from the map's area to solid angle
+        Some((dir, pdf_u * pdf_v / (2.0 * PI * PI * sin_theta)))
+    }
+
+    fn pdf(&self, dir: Vec3) -> FloatT {
+        let (u, v) = self.to_uv(dir);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.texel(u, v);
+        self.marginal.density(y) * self.rows[y].density(x) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Angular diameter of the sun seen from the ground, in degrees.
+pub const SUN_ANGLE: FloatT = 0.53;
+
+// Preetham's luminance is in kcd/m², which is far brighter than the rest of the scene
+const SKY_SCALE: FloatT = 0.05;
+
+// Lowest turbidity the fitted model is valid for
+const MIN_TURBIDITY: FloatT = 1.7;
+
+// Coefficients A through E of the Perez distribution for luminance and the two chromaticities,
+// each linear in turbidity
+fn perez_coefficients(t: FloatT) -> [[FloatT; 5]; 3] {
+    [
+        [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+        [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+        [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]
+    ]
+}
+
+// Relative brightness of a direction `theta` from the zenith and `gamma` from the sun
+fn perez(c: &[FloatT; 5], cos_theta: FloatT, gamma: FloatT) -> FloatT {
+    let cos_gamma = gamma.cos();
+    (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+// Luminance `lum` with chromaticity `x`, `y` in linear sRGB
+fn xyy_to_rgb(x: FloatT, y: FloatT, lum: FloatT) -> Color {
+    let cx = x / y * lum;
+    let cz = (1.0 - x - y) / y * lum;
+    Color::new(3.2406 * cx - 1.5372 * lum - 0.4986 * cz,
+               -0.9689 * cx + 1.8758 * lum + 0.0415 * cz,
+               0.0557 * cx - 0.2040 * lum + 1.0570 * cz)
+}
+
+/// Daylight sky from Preetham, Shirley and Smits' analytic model, lit by a sun at a given
+/// position through air as hazy as its turbidity.
+pub struct PreethamSky {
+    sun: Vec3,
+    turbidity: FloatT,
+    intensity: FloatT,
+    coefficients: [[FloatT; 5]; 3],
+    // Luminance and chromaticity straight up, each divided by the Perez function there
+    zenith: [FloatT; 3]
+}
+
+impl PreethamSky {
+    /// Creates a sky with the sun `elevation` degrees above the horizon and `azimuth` degrees
+    /// around from +x towards +z. `turbidity` is 2 for clear air and 10 or so for haze, and the
+    /// sky's radiance is scaled by `intensity`.
+    pub fn new(elevation: FloatT, azimuth: FloatT, turbidity: FloatT, intensity: FloatT) -> PreethamSky {
+        let (el, az) = (elevation.to_radians(), azimuth.to_radians());
+        let sun = Vec3::new(el.cos() * az.cos(), el.sin(), el.cos() * az.sin());
+        let t = turbidity.max(MIN_TURBIDITY);
+        // The fits don't hold once the sun sets
+        let theta_s = (PI / 2.0 - el).clamp(0.0, PI / 2.0);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let lum = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (s, s2, s3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        let coefficients = perez_coefficients(t);
+        let mut zenith = [lum.max(0.0), x, y];
+        for (z, c) in zenith.iter_mut().zip(&coefficients) {
+            *z /= perez(c, 1.0, theta_s);
+        }
+
+        PreethamSky {
+            sun,
+            turbidity: t,
+            intensity,
+            coefficients,
+            zenith
+        }
+    }
+
+    /// Unit direction towards the sun.
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun
+    }
+
+    /// Fraction of red, green and blue sunlight that makes it through the atmosphere, which
+    /// reddens the sun as it nears the horizon.
+    pub fn sun_color(&self) -> Color {
+        let theta_s = self.sun.y().clamp(0.0, 1.0).acos();
+        // Relative optical mass of the air the light passes through, after Kasten
+        let m = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        // Rayleigh scattering by air and Ångström's aerosol scattering at a wavelength per channel
+        let transmittance = |lambda: FloatT| {
+            (-0.008735 * lambda.powf(-4.08) * m).exp() * (-beta * lambda.powf(-1.3) * m).exp()
+        };
+        Color::new(transmittance(0.65), transmittance(0.57), transmittance(0.475))
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, dir: Vec3) -> Color {
+        // Below the horizon looks like the horizon
+        let cos_theta = dir.y().max(1.0e-3);
+        let gamma = dir.dot(self.sun).clamp(-1.0, 1.0).acos();
+        let [lum, x, y] = [0, 1, 2].map(|i| self.zenith[i] * perez(&self.coefficients[i], cos_theta, gamma));
+        let c = xyy_to_rgb(x, y, lum) * SKY_SCALE * self.intensity;
+        Color::new(c.x().max(0.0), c.y().max(0.0), c.z().max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::sample_unit_sphere;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn map_pdf_integrates_to_one_and_matches_sampling() {
+        // A dim map with one bright texel, so the density is far from uniform
+        let mut image = Image::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                image.set(x, y, Color::new(0.1 * (x + 1) as FloatT, 0.2, 0.05 * (y + 1) as FloatT));
+            }
+        }
+        image.set(5, 1, Color::new(20.0, 20.0, 20.0));
+        let map = EnvironmentMap::new(image, 30.0, 1.0);
+
+        let mut rng = StdRng::seed_from_u64(5);
+        let n = 400_000;
+        let sum: FloatT = (0..n).map(|_| map.pdf(sample_unit_sphere((rng.gen(), rng.gen())))).sum();
+        let integral = 4.0 * PI * sum / n as FloatT;
+        assert!((integral - 1.0).abs() < 0.03, "pdf integrates to {}", integral);
+
+        for _ in 0..100 {
+            let (dir, pdf) = map.sample((rng.gen(), rng.gen())).unwrap();
+            assert!((map.pdf(dir) - pdf).abs() < 1e-6 * pdf, "sampled with {} but pdf gives {}", pdf, map.pdf(dir));
+        }
+    }
+}
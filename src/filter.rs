@@ -0,0 +1,164 @@
+use super::vec::FloatT;
+
+use std::f64::consts::PI;
+
+/// Shape of a pixel reconstruction filter.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterKind {
+    /// Equal weight everywhere inside the radius. A radius of 0.5 averages the samples in each pixel.
+    Box,
+    /// Weight falling linearly to zero at the radius.
+    Triangle,
+    /// Gaussian with standard deviation `sigma`, shifted down to reach zero at the radius.
+    Gaussian {
+        /// Standard deviation in pixels.
+        sigma: FloatT
+    },
+    /// The Mitchell-Netravali cubic stretched over the radius, with a slightly negative lobe.
+    Mitchell {
+        /// Blurring parameter.
+        b: FloatT,
+        /// Ringing parameter.
+        c: FloatT
+    },
+    /// Sinc windowed by a wider sinc so it ends at the radius. The sharpest but may ring.
+    Lanczos
+}
+
+// Intervals of the Simpson's rule that normalizes filters, a multiple of 4 so the kinks of the
+// triangle and Mitchell filters fall between panels
+const NORMALIZE_STEPS: usize = 1024;
+
+/// A separable pixel reconstruction filter. Every sample is splatted into each pixel whose center
+/// lies within `radius` pixels in both directions, weighted by the filter.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: FloatT,
+    // Makes the filter integrate to 1 over its support
+    scale: FloatT
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+fn sinc(x: FloatT) -> FloatT {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn mitchell(x: FloatT, b: FloatT, c: FloatT) -> FloatT {
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x.powi(3) + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+impl Filter {
+    /// Creates a filter with the usual radius for `kind`: 0.5 for box, 1 for triangle, 1.5 for
+    /// Gaussian, 2 for Mitchell and 3 for Lanczos.
+    pub fn new(kind: FilterKind) -> Filter {
+        let radius = match kind {
+            FilterKind::Box => 0.5,
+            FilterKind::Triangle => 1.0,
+            FilterKind::Gaussian { .. } => 1.5,
+            FilterKind::Mitchell { .. } => 2.0,
+            FilterKind::Lanczos => 3.0
+        };
+        Filter::with_radius(kind, radius)
+    }
+
+    /// Creates a filter of shape `kind` reaching `radius` pixels from the pixel center.
+    pub fn with_radius(kind: FilterKind, radius: FloatT) -> Filter {
+        let mut filter = Filter { kind, radius, scale: 1.0 };
+        let h = 2.0 * radius / NORMALIZE_STEPS as FloatT;
+        let integral: FloatT = (0..=NORMALIZE_STEPS).map(|i| {
+            let w = if i == 0 || i == NORMALIZE_STEPS { 1.0 } else if i % 2 == 1 { 4.0 } else { 2.0 };
+            w * filter.eval_1d(-radius + i as FloatT * h)
+        }).sum::<FloatT>() * h / 3.0;
+        // The filter is separable, so its 2D integral is the square of the 1D one
+        filter.scale = 1.0 / (integral * integral);
+        filter
+    }
+
+    /// Shape of the filter.
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    /// Half width of the filter's support in pixels.
+    pub fn radius(&self) -> FloatT {
+        self.radius
+    }
+
+    fn eval_1d(&self, x: FloatT) -> FloatT {
+        let r = self.radius;
+        if x.abs() > r {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Triangle => r - x.abs(),
+            FilterKind::Gaussian { sigma } => {
+                let g = |x: FloatT| (-x * x / (2.0 * sigma * sigma)).exp();
+                (g(x) - g(r)).max(0.0)
+            }
+            FilterKind::Mitchell { b, c } => mitchell(2.0 * x / r, b, c),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r)
+        }
+    }
+
+    /// Weight of a sample `(dx, dy)` pixels away from a pixel center, normalized so the filter
+    /// integrates to 1.
+    pub fn eval(&self, dx: FloatT, dy: FloatT) -> FloatT {
+        self.scale * self.eval_1d(dx) * self.eval_1d(dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [FilterKind::Box, FilterKind::Triangle, FilterKind::Gaussian { sigma: 0.5 },
+                                    FilterKind::Mitchell { b: 1.0 / 3.0, c: 1.0 / 3.0 }, FilterKind::Lanczos];
+
+    // Midpoint rule over the filter's square support
+    fn integral(filter: &Filter) -> FloatT {
+        let n = 400;
+        let h = 2.0 * filter.radius() / n as FloatT;
+        let at = |i: usize| -filter.radius() + (i as FloatT + 0.5) * h;
+        (0..n * n).map(|k| filter.eval(at(k % n), at(k / n))).sum::<FloatT>() * h * h
+    }
+
+    #[test]
+    fn filters_integrate_to_one() {
+        for kind in KINDS {
+            for filter in [Filter::new(kind), Filter::with_radius(kind, 1.25)] {
+                let i = integral(&filter);
+                assert!((i - 1.0).abs() < 1e-3, "{:?} integrates to {}", filter, i);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_outside_the_radius() {
+        for kind in KINDS {
+            let filter = Filter::new(kind);
+            let r = filter.radius();
+            assert_eq!(filter.eval(r + 1e-6, 0.0), 0.0, "{:?}", kind);
+            assert_eq!(filter.eval(0.0, -r - 1e-6), 0.0, "{:?}", kind);
+            assert!(filter.eval(0.0, 0.0) > 0.0, "{:?}", kind);
+        }
+    }
+}
@@ -1,19 +1,27 @@
 use super::vec::{Vec3, Point3, FloatT};
 use super::ray::Ray;
-use super::material::Scatter;
+use super::material::BSDF;
+use super::aabb::AABB;
 
 use std::sync::Arc;
 
+/// Where and how a ray hit a surface.
 pub struct HitRecord {
+    /// Point of intersection.
     pub p: Point3,
+    /// Unit surface normal, always facing against the incoming ray.
     pub normal: Vec3,
-    pub mat: Arc<dyn Scatter>,
+    /// Material of the surface that was hit.
+    pub mat: Arc<dyn BSDF>,
+    /// Ray parameter of the intersection.
     pub t: FloatT,
+    /// True if the ray hit the outside of the surface.
     pub front_face: bool
 }
 
 impl HitRecord {
-    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) -> () {
+    /// Stores the normal facing against `r` and records which side was hit.
+    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         // If the ray dot our normal < 0, the ray is coming from the outside
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal = if self.front_face {
@@ -24,11 +32,49 @@ impl HitRecord {
     }
 }
 
+/// Anything a ray can intersect.
 pub trait Hit : Send + Sync {
+    /// Finds the closest intersection with `r` in `(t_min, t_max)`.
     fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> (bool, Option<HitRecord>);
-    //fn bounding_box(&self) -> Option<AABB>;
+
+    /// World-space bounds of the object, None if it's unbounded (e.g. an infinite plane).
+    fn bounding_box(&self) -> Option<AABB>;
+
+    /// True if `r` hits anything in `(t_min, t_max)`. Unlike [`Hit::hit`] this may stop at the
+    /// first intersection found and skips building a [`HitRecord`].
+    fn hit_any(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> bool {
+        self.hit(r, t_min, t_max).1.is_some()
+    }
+
+    /// True if anything blocks the straight line from `origin` to `target`, ignoring surfaces
+    /// within [`SHADOW_EPSILON`] of either end.
+    fn occluded(&self, origin: Point3, target: Point3) -> bool {
+        let to_target = target - origin;
+        let dist = to_target.length();
+        // With a unit direction t is the distance along the segment
+        let r = Ray::new(origin, to_target / dist);
+        self.hit_any(&r, SHADOW_EPSILON, dist - SHADOW_EPSILON)
+    }
+}
+
+impl<T: Hit + ?Sized> Hit for Arc<T> {
+    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> (bool, Option<HitRecord>) {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        (**self).bounding_box()
+    }
+
+    fn hit_any(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> bool {
+        (**self).hit_any(r, t_min, t_max)
+    }
 }
 
+/// Distance shadow rays skip at both ends, so surfaces don't shadow themselves.
+pub const SHADOW_EPSILON: FloatT = 0.001;
+
+/// A list of objects, intersected by testing each one in turn.
 pub type World = Vec<Box<dyn Hit>>;
 
 impl Hit for World {
@@ -38,11 +84,19 @@ impl Hit for World {
 
         // Find the closest object that the ray intersects
         for object in self {
-            if let (b, Some(rec)) = object.hit(r, t_min, closest) {
+            if let (_, Some(rec)) = object.hit(r, t_min, closest) {
                 closest = rec.t;
                 tmp_rec = Some(rec);
             }
         }
         (true, tmp_rec)
     }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        AABB::surrounding_boxes(self.iter().map(|object| object.bounding_box()))
+    }
+
+    fn hit_any(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> bool {
+        self.iter().any(|object| object.hit_any(r, t_min, t_max))
+    }
 }
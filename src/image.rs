@@ -0,0 +1,109 @@
+use super::vec::{Color, FloatT};
+
+/// A named per-pixel value stored alongside the color, such as depth.
+pub struct Channel {
+    /// Name of the channel, e.g. `Z` or `N.X`.
+    pub name: String,
+    /// One value per pixel in the same order as the image's pixels.
+    pub data: Vec<FloatT>
+}
+
+/// A framebuffer of linear, high dynamic range colors, stored row by row starting from the top-left pixel.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    channels: Vec<Channel>
+}
+
+impl Image {
+    /// Creates a black image.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height],
+            channels: Vec::new()
+        }
+    }
+
+    /// Wraps existing pixels, which must hold exactly `width * height` colors.
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+        assert_eq!(pixels.len(), width * height, "pixel count doesn't match the image size");
+        Image {
+            width,
+            height,
+            pixels,
+            channels: Vec::new()
+        }
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> usize { self.width }
+    /// Height in pixels.
+    pub fn height(&self) -> usize { self.height }
+
+    /// Color of the pixel in column `x` of row `y`, counting rows from the top.
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Sets the pixel in column `x` of row `y`, counting rows from the top.
+    pub fn set(&mut self, x: usize, y: usize, c: Color) {
+        self.pixels[y * self.width + x] = c;
+    }
+
+    /// All pixels, top row first.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Mutable access to all pixels, top row first.
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    /// Attaches an extra channel, `data` must hold one value per pixel.
+    pub fn add_channel(&mut self, name: &str, data: Vec<FloatT>) {
+        assert_eq!(data.len(), self.width * self.height, "channel size doesn't match the image size");
+        self.channels.push(Channel {
+            name: name.to_string(),
+            data
+        });
+    }
+
+    /// Extra channels in the order they were added. Only some formats can store them.
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    /// Detaches and returns the extra channel called `name`, if there is one.
+    pub fn remove_channel(&mut self, name: &str) -> Option<Channel> {
+        let i = self.channels.iter().position(|c| c.name == name)?;
+        Some(self.channels.remove(i))
+    }
+
+    /// The extra channel called `name`, if there is one.
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.iter().find(|c| c.name == name)
+    }
+
+    /// False color visualization of the channel `name`, running from black at zero through purple
+    /// and orange to pale yellow at the channel's largest finite value.
+    pub fn heatmap(&self, name: &str) -> Option<Image> {
+        const RAMP: [(FloatT, FloatT, FloatT); 5] = [(0.0, 0.0, 0.0), (0.1, 0.0, 0.4), (0.7, 0.1, 0.3),
+                                                     (1.0, 0.5, 0.0), (1.0, 1.0, 0.6)];
+
+        let data = &self.channel(name)?.data;
+        let max = data.iter().copied().filter(|v| v.is_finite()).fold(0.0, FloatT::max);
+        let pixels = data.iter().map(|&v| {
+            let t = if max > 0.0 && v.is_finite() { (v / max).clamp(0.0, 1.0) } else { 0.0 };
+            let f = t * (RAMP.len() - 1) as FloatT;
+            let k = (f as usize).min(RAMP.len() - 2);
+            let (a, b) = (RAMP[k], RAMP[k + 1]);
+            let w = f - k as FloatT;
+            Color::new(a.0 + w * (b.0 - a.0), a.1 + w * (b.1 - a.1), a.2 + w * (b.2 - a.2))
+        }).collect();
+        Some(Image::from_pixels(self.width, self.height, pixels))
+    }
+}
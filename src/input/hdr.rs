@@ -0,0 +1,280 @@
+use super::{ImageReader, invalid};
+use crate::image::Image;
+use crate::vec::{Color, FloatT};
+
+use std::io::{self, BufRead};
+
+/// Radiance `.hdr` files with RGBE pixels, flat, with old-style repeats or with per-channel run-length encoding.
+pub struct HDRReader;
+
+// Scanline widths that can use per-channel run-length encoding
+const RLE_MIN_WIDTH: usize = 8;
+const RLE_MAX_WIDTH: usize = 0x7fff;
+
+// Consecutive repeats shift their counts 8 bits further each, a fourth would be past any real width
+const MAX_REPEAT_SHIFT: u32 = 24;
+
+fn rgbe_to_color(p: [u8; 4]) -> Color {
+    if p[3] == 0 {
+        return Color::default();
+    }
+    // The exponent is shared by all three mantissas, which are 8-bit fractions
+    let f = (2.0 as FloatT).powi(p[3] as i32 - (128 + 8));
+    Color::new(p[0] as FloatT * f, p[1] as FloatT * f, p[2] as FloatT * f)
+}
+
+fn read_byte(input: &mut dyn BufRead) -> io::Result<u8> {
+    let mut b = [0u8];
+    input.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+// Reads a scanline of pixels that are stored whole, where a pixel of 1,1,1 repeats the previous
+// one with the exponent as the count, shifted further by each consecutive repeat
+fn read_flat(input: &mut dyn BufRead, first: [u8; 4], width: usize, line: &mut Vec<[u8; 4]>) -> io::Result<()> {
+    // The line grows as pixels arrive so a huge width in the header isn't allocated up front
+    line.clear();
+    let mut shift = 0;
+    let mut pixel = first;
+    loop {
+        if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 {
+            let Some(&previous) = line.last() else {
+                return invalid("HDR repeat with no pixel before it");
+            };
+            if shift >= MAX_REPEAT_SHIFT {
+                return invalid("too many HDR repeats in a row");
+            }
+            let count = (pixel[3] as usize) << shift;
+            if count == 0 {
+                return invalid("HDR repeat with a zero count");
+            }
+            if count > width - line.len() {
+                return invalid("HDR repeat past the end of the scanline");
+            }
+            line.resize(line.len() + count, previous);
+            shift += 8;
+        } else {
+            line.push(pixel);
+            shift = 0;
+        }
+        if line.len() == width {
+            return Ok(());
+        }
+        input.read_exact(&mut pixel)?;
+    }
+}
+
+// Reads a scanline whose four byte planes are each run-length encoded
+fn read_rle(input: &mut dyn BufRead, line: &mut [[u8; 4]]) -> io::Result<()> {
+    for c in 0..4 {
+        let mut x = 0;
+        while x < line.len() {
+            let count = read_byte(input)? as usize;
+            if count > 128 {
+                let run = count - 128;
+                if run > line.len() - x {
+                    return invalid("HDR run past the end of the scanline");
+                }
+                let value = read_byte(input)?;
+                for p in &mut line[x..x + run] {
+                    p[c] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || count > line.len() - x {
+                    return invalid("bad HDR run length");
+                }
+                for p in &mut line[x..x + count] {
+                    p[c] = read_byte(input)?;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ImageReader for HDRReader {
+    fn read(&self, input: &mut dyn BufRead) -> io::Result<Image> {
+        let mut header = String::new();
+        input.read_line(&mut header)?;
+        if !header.starts_with("#?") {
+            return invalid("not a Radiance HDR file");
+        }
+
+        // Header variables end at an empty line
+        loop {
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return invalid("HDR header has no end");
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return invalid("only RGBE HDR files are supported");
+                }
+            }
+        }
+
+        // Rows are stored top to bottom for -Y and bottom to top for +Y
+        let mut resolution = String::new();
+        input.read_line(&mut resolution)?;
+        let fields: Vec<&str> = resolution.split_whitespace().collect();
+        let (flip, height, width) = match fields.as_slice() {
+            ["-Y", h, "+X", w] => (false, h.parse::<usize>(), w.parse::<usize>()),
+            ["+Y", h, "+X", w] => (true, h.parse::<usize>(), w.parse::<usize>()),
+            _ => return invalid("unsupported HDR orientation")
+        };
+        let (Ok(height), Ok(width)) = (height, width) else {
+            return invalid("bad HDR size");
+        };
+        if width == 0 || height == 0 {
+            return invalid("HDR image is empty");
+        }
+        if width.checked_mul(height).is_none() {
+            return invalid("HDR size is too large");
+        }
+
+        // Pixels are gathered as scanlines arrive so a truncated file can't claim a huge image
+        let mut pixels = Vec::new();
+        let mut line = Vec::new();
+        for _ in 0..height {
+            let mut start = [0u8; 4];
+            input.read_exact(&mut start)?;
+            // Run-length encoded scanlines start with 2, 2 and the width
+            let rle = (RLE_MIN_WIDTH..=RLE_MAX_WIDTH).contains(&width)
+                && start[0] == 2 && start[1] == 2 && ((start[2] as usize) << 8 | start[3] as usize) == width;
+            if rle {
+                line.resize(width, [0; 4]);
+                read_rle(input, &mut line)?;
+            } else {
+                read_flat(input, start, width, &mut line)?;
+            }
+
+            pixels.extend(line.iter().map(|p| rgbe_to_color(*p)));
+        }
+        if flip {
+            pixels.reverse();
+            for row in pixels.chunks_mut(width) {
+                row.reverse();
+            }
+        }
+        Ok(Image::from_pixels(width, height, pixels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n";
+
+    // RGBE for 1, 0.5 and 0.25
+    const PIXEL: [u8; 4] = [128, 64, 32, 129];
+
+    fn rgb(c: Color) -> [FloatT; 3] {
+        [c.x(), c.y(), c.z()]
+    }
+
+    fn read(resolution: &str, data: &[u8]) -> io::Result<Image> {
+        let mut file = HEADER.to_vec();
+        file.extend_from_slice(resolution.as_bytes());
+        file.extend_from_slice(data);
+        HDRReader.read(&mut &file[..])
+    }
+
+    fn error(resolution: &str, data: &[u8]) -> String {
+        let err = read(resolution, data).err().expect("malformed HDR was accepted");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn rgbe_decoding() {
+        assert_eq!(rgb(rgbe_to_color(PIXEL)), [1.0, 0.5, 0.25]);
+        assert_eq!(rgb(rgbe_to_color([255, 255, 255, 0])), [0.0; 3]);
+    }
+
+    #[test]
+    fn flat_with_repeats() {
+        // The first row is one pixel repeated twice, the second three distinct pixels
+        let data = [PIXEL, [1, 1, 1, 2], [0, 0, 0, 0], [128, 0, 0, 130], [0, 128, 0, 128]].concat();
+        let image = read("-Y 2 +X 3\n", &data).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        for x in 0..3 {
+            assert_eq!(rgb(image.get(x, 0)), [1.0, 0.5, 0.25]);
+        }
+        assert_eq!(rgb(image.get(0, 1)), [0.0; 3]);
+        assert_eq!(rgb(image.get(1, 1)), [2.0, 0.0, 0.0]);
+        assert_eq!(rgb(image.get(2, 1)), [0.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn bottom_to_top_rows_are_flipped() {
+        let data = [PIXEL, [0, 0, 0, 0], [0, 0, 0, 0], [128, 0, 0, 130]].concat();
+        let image = read("+Y 2 +X 2\n", &data).unwrap();
+        assert_eq!(rgb(image.get(0, 0)), [0.0; 3]);
+        assert_eq!(rgb(image.get(1, 0)), [2.0, 0.0, 0.0]);
+        assert_eq!(rgb(image.get(0, 1)), [1.0, 0.5, 0.25]);
+        assert_eq!(rgb(image.get(1, 1)), [0.0; 3]);
+    }
+
+    #[test]
+    fn run_length_encoded() {
+        // Red and exponent are runs, green half a run and half literals, blue all literals
+        let mut data = vec![2, 2, 0, 8];
+        data.extend_from_slice(&[136, 128]);
+        data.extend_from_slice(&[132, 64, 4, 0, 0, 0, 64]);
+        data.extend_from_slice(&[8, 32, 32, 32, 32, 16, 16, 16, 16]);
+        data.extend_from_slice(&[136, 129]);
+        let image = read("-Y 1 +X 8\n", &data).unwrap();
+        assert_eq!(rgb(image.get(0, 0)), [1.0, 0.5, 0.25]);
+        assert_eq!(rgb(image.get(5, 0)), [1.0, 0.0, 0.125]);
+        assert_eq!(rgb(image.get(7, 0)), [1.0, 0.5, 0.125]);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert!(HDRReader.read(&mut &b"P6\n"[..]).is_err());
+        assert_eq!(error("-Y 0 +X 4\n", &[]), "HDR image is empty");
+        assert_eq!(error("-X 2 +Y 2\n", &[]), "unsupported HDR orientation");
+        assert_eq!(error("-Y two +X 2\n", &[]), "bad HDR size");
+        assert_eq!(error("-Y 99999999999 +X 99999999999\n", &[]), "HDR size is too large");
+    }
+
+    #[test]
+    fn huge_width_is_not_allocated_up_front() {
+        let err = read("-Y 1 +X 1000000000000\n", &PIXEL).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn bad_repeats() {
+        assert_eq!(error("-Y 1 +X 4\n", &[1, 1, 1, 2]), "HDR repeat with no pixel before it");
+        assert_eq!(error("-Y 1 +X 4\n", &[PIXEL, [1, 1, 1, 0]].concat()), "HDR repeat with a zero count");
+        assert_eq!(error("-Y 1 +X 4\n", &[PIXEL, [1, 1, 1, 4]].concat()),
+                   "HDR repeat past the end of the scanline");
+
+        // Counts of 1, 1 << 8 and 1 << 16 fit the scanline, a fourth repeat would shift by 24
+        let data = [PIXEL, [1, 1, 1, 1], [1, 1, 1, 1], [1, 1, 1, 1], [1, 1, 1, 1]].concat();
+        assert_eq!(error("-Y 1 +X 70000\n", &data), "too many HDR repeats in a row");
+    }
+
+    #[test]
+    fn bad_runs() {
+        assert_eq!(error("-Y 1 +X 8\n", &[2, 2, 0, 8, 137, 0]), "HDR run past the end of the scanline");
+        assert_eq!(error("-Y 1 +X 8\n", &[2, 2, 0, 8, 0]), "bad HDR run length");
+        assert_eq!(error("-Y 1 +X 8\n", &[2, 2, 0, 8, 4, 0, 0, 0, 0, 5]), "bad HDR run length");
+    }
+
+    #[test]
+    fn truncated_data() {
+        let err = read("-Y 2 +X 2\n", &[PIXEL, PIXEL, PIXEL].concat()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = read("-Y 1 +X 8\n", &[2, 2, 0, 8, 136]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
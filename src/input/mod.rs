@@ -0,0 +1,48 @@
+use super::image::Image;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+mod hdr;
+mod pfm;
+
+pub use hdr::HDRReader;
+pub use pfm::PFMReader;
+
+/// Decodes an [`Image`] from some file format.
+pub trait ImageReader {
+    /// Reads a whole encoded image from `input`.
+    fn read(&self, input: &mut dyn BufRead) -> io::Result<Image>;
+}
+
+/// Picks a reader from the extension of `path`, matched case-insensitively:
+///
+/// | Extension | Format |
+/// |-----------|--------|
+/// | `.hdr`    | Radiance RGBE, flat or run-length encoded |
+/// | `.pfm`    | Portable float map, color or grayscale |
+pub fn reader_for_path(path: &Path) -> Option<Box<dyn ImageReader>> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "hdr" => Some(Box::new(HDRReader)),
+        "pfm" => Some(Box::new(PFMReader)),
+        _ => None
+    }
+}
+
+/// Reads the image at `path` in the format chosen by [`reader_for_path`].
+pub fn load(path: &Path) -> io::Result<Image> {
+    let Some(reader) = reader_for_path(path) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("unsupported image format '{}'", path.display())));
+    };
+
+    let mut input = BufReader::new(File::open(path)?);
+    reader.read(&mut input)
+}
+
+// Error for malformed files
+fn invalid<T>(msg: &str) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, msg.to_string()))
+}
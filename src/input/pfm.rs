@@ -0,0 +1,161 @@
+use super::{ImageReader, invalid};
+use crate::image::Image;
+use crate::vec::{Color, FloatT};
+
+use std::io::{self, BufRead, Read};
+
+/// Portable float map, `PF` with RGB or `Pf` with one gray channel, in either byte order.
+pub struct PFMReader;
+
+// Next whitespace separated header field, consuming the single whitespace byte that ends it
+fn field(input: &mut dyn BufRead) -> io::Result<String> {
+    let mut text = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        input.read_exact(&mut byte)?;
+        if byte[0].is_ascii_whitespace() {
+            if text.is_empty() {
+                continue;
+            }
+            break;
+        }
+        text.push(byte[0]);
+    }
+    match String::from_utf8(text) {
+        Ok(s) => Ok(s),
+        Err(_) => invalid("PFM header isn't text")
+    }
+}
+
+impl ImageReader for PFMReader {
+    fn read(&self, input: &mut dyn BufRead) -> io::Result<Image> {
+        let channels = match field(input)?.as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return invalid("not a PFM file")
+        };
+        let (Ok(width), Ok(height)) = (field(input)?.parse::<usize>(), field(input)?.parse::<usize>()) else {
+            return invalid("bad PFM size");
+        };
+        // Only the sign of the scale matters, negative means little endian
+        let Ok(scale) = field(input)?.parse::<f32>() else {
+            return invalid("bad PFM scale");
+        };
+
+        if width == 0 || height == 0 {
+            return invalid("PFM image is empty");
+        }
+        let Some(len) = width.checked_mul(height).and_then(|n| n.checked_mul(channels * 4)) else {
+            return invalid("PFM size is too large");
+        };
+
+        // Read what's there rather than trusting the header with one huge allocation
+        let mut data = Vec::new();
+        input.take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return invalid("PFM data is shorter than its size");
+        }
+        let value = |i: usize| {
+            let bytes = [data[4 * i], data[4 * i + 1], data[4 * i + 2], data[4 * i + 3]];
+            let v = if scale < 0.0 { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) };
+            v as FloatT
+        };
+        // Infinities and NaNs would poison importance sampling and every pixel they're filtered into
+        if (0..len / 4).any(|i| !value(i).is_finite()) {
+            return invalid("PFM has non-finite values");
+        }
+
+        // PFM stores rows bottom to top
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let i = ((height - 1 - y) * width + x) * channels;
+                let c = if channels == 3 {
+                    Color::new(value(i), value(i + 1), value(i + 2))
+                } else {
+                    Color::new(value(i), value(i), value(i))
+                };
+                image.set(x, y, c);
+            }
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(c: Color) -> [FloatT; 3] {
+        [c.x(), c.y(), c.z()]
+    }
+
+    fn pfm(header: &str, values: &[f32], little_endian: bool) -> Vec<u8> {
+        let mut file = header.as_bytes().to_vec();
+        for v in values {
+            file.extend_from_slice(&if little_endian { v.to_le_bytes() } else { v.to_be_bytes() });
+        }
+        file
+    }
+
+    fn error(file: &[u8]) -> String {
+        let err = PFMReader.read(&mut &file[..]).err().expect("malformed PFM was accepted");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn color_in_both_byte_orders() {
+        // Two rows stored bottom first
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 0.5, 0.25, 0.125, -1.0, 0.0, 8.0];
+        for (header, little_endian) in [("PF\n2 2\n-1.0\n", true), ("PF\n2 2\n1.0\n", false)] {
+            let image = PFMReader.read(&mut &pfm(header, &values, little_endian)[..]).unwrap();
+            assert_eq!((image.width(), image.height()), (2, 2));
+            assert_eq!(rgb(image.get(0, 0)), [0.5, 0.25, 0.125]);
+            assert_eq!(rgb(image.get(1, 0)), [-1.0, 0.0, 8.0]);
+            assert_eq!(rgb(image.get(0, 1)), [1.0, 2.0, 3.0]);
+            assert_eq!(rgb(image.get(1, 1)), [4.0, 5.0, 6.0]);
+        }
+    }
+
+    #[test]
+    fn grayscale() {
+        let file = pfm("Pf 3 1 -1\n", &[0.5, 1.5, 2.5], true);
+        let image = PFMReader.read(&mut &file[..]).unwrap();
+        assert_eq!(rgb(image.get(1, 0)), [1.5; 3]);
+        assert_eq!(rgb(image.get(2, 0)), [2.5; 3]);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert_eq!(error(b"P6\n1 1\n255\n"), "not a PFM file");
+        assert_eq!(error(b"PF\n1 x\n-1\n"), "bad PFM size");
+        assert_eq!(error(b"PF\n1 1\nbig\n"), "bad PFM scale");
+        assert_eq!(error(b"PF\n0 0\n-1\n"), "PFM image is empty");
+        assert_eq!(error(b"PF\n0 4\n-1\n"), "PFM image is empty");
+    }
+
+    #[test]
+    fn huge_sizes_are_rejected_without_allocating() {
+        assert_eq!(error(b"PF\n18446744073709551615 2\n-1\n"), "PFM size is too large");
+        // Fits in a usize but the file doesn't have the data
+        assert_eq!(error(b"PF\n1000000 1000000\n-1\n\0\0\0\0"), "PFM data is shorter than its size");
+    }
+
+    #[test]
+    fn non_finite_values() {
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let file = pfm("PF\n2 1\n-1\n", &[1.0, 2.0, 3.0, 4.0, bad, 6.0], true);
+            assert_eq!(error(&file), "PFM has non-finite values");
+        }
+    }
+
+    #[test]
+    fn truncated_data() {
+        let file = pfm("PF\n2 1\n-1\n", &[1.0, 2.0, 3.0, 4.0, 5.0], true);
+        assert_eq!(error(&file), "PFM data is shorter than its size");
+        // A header cut off before its last field
+        let err = PFMReader.read(&mut &b"PF\n2 1"[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,62 @@
+//! A small path tracer following Ray Tracing in One Weekend.
+//!
+//! Scenes are built from [`hit::Hit`] objects such as [`sphere::Sphere`], shaded with the
+//! [`material`] scatter models and viewed through a [`camera::Camera`]. A [`render::Renderer`]
+//! traces the scene into an in-memory [`image::Image`]:
+//!
+//! ```no_run
+//! use raytracing::scene::Scene;
+//! use raytracing::bvh::BVHTree;
+//! use raytracing::render::Renderer;
+//!
+//! let scene = Scene::load("scenes/debug.scene").unwrap();
+//! let camera = scene.camera.build(16.0 / 9.0);
+//! let world = BVHTree::new(scene.world);
+//!
+//! let image = Renderer::new(400, 225).render(&world, &scene.lights, camera.as_ref());
+//! println!("{}", image.get(200, 112));
+//! ```
+
+#![warn(missing_docs)]
+#![allow(clippy::upper_case_acronyms)]
+
+/// 3D vectors, points and colors.
+pub mod vec;
+/// Rays with an origin and direction.
+pub mod ray;
+/// The `Hit` trait for ray-object intersection and the `World` object list.
+pub mod hit;
+/// Sphere geometry.
+pub mod sphere;
+/// Rectangle geometry.
+pub mod rect;
+/// Cameras that generate primary rays: perspective with a thin lens, orthographic, fisheye and panoramic.
+pub mod camera;
+/// Materials describing how rays scatter off surfaces.
+pub mod material;
+/// Point and area lights, and sampling them for direct lighting.
+pub mod light;
+/// Distant surroundings lighting the scene, such as a sky gradient, a daylight sky or an HDR map.
+pub mod environment;
+/// Axis-aligned bounding boxes.
+pub mod aabb;
+/// Bounding volume hierarchy for accelerating ray intersection.
+pub mod bvh;
+/// Loading scenes from text description files.
+pub mod scene;
+/// In-memory images.
+pub mod image;
+/// Tone mapping and sRGB encoding of HDR radiance for display formats.
+pub mod tonemap;
+/// Tracing a scene into an image.
+pub mod render;
+/// Pixel reconstruction filters.
+pub mod filter;
+/// Random and low-discrepancy sample sequences.
+pub mod sampler;
+/// Splitting images into tiles and ordering them for rendering.
+pub mod tile;
+/// Writing images to disk in various file formats, including a DEFLATE encoder.
+pub mod output;
+/// Reading high dynamic range images from disk.
+pub mod input;
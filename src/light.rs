@@ -1,70 +1,570 @@
 use super::vec::{Point3, Vec3, FloatT, Color};
 use super::ray::Ray;
-use super::hit::{HitRecord, World};
+use super::hit::{HitRecord, Hit};
+use super::sampler::{Sampler, sample_cone};
+use super::environment::Environment;
 
-pub struct Light {
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// How light sampling and BSDF sampling are weighted where both can find the same light.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MISHeuristic {
+    /// Weights each strategy by its share of the summed densities.
+    Balance,
+    /// Like the balance heuristic on squared densities, favoring whichever strategy is clearly better.
+    Power,
+    /// Only light sampling finds lights that BSDF sampling could also reach, for comparison.
+    LightOnly,
+    /// Only BSDF sampling finds lights that it can reach, for comparison.
+    BSDFOnly
+}
+
+impl MISHeuristic {
+    /// Weight of a light sample picked with density `light_pdf` when BSDF sampling would pick it with `bsdf_pdf`.
+    pub fn light_weight(self, light_pdf: FloatT, bsdf_pdf: FloatT) -> FloatT {
+        match self {
+            MISHeuristic::LightOnly => 1.0,
+            MISHeuristic::BSDFOnly => if bsdf_pdf > 0.0 { 0.0 } else { 1.0 },
+            _ => self.weight(light_pdf, bsdf_pdf)
+        }
+    }
+
+    /// Weight of a BSDF sample picked with density `bsdf_pdf` when light sampling would pick it with `light_pdf`.
+    pub fn bsdf_weight(self, bsdf_pdf: FloatT, light_pdf: FloatT) -> FloatT {
+        match self {
+            MISHeuristic::BSDFOnly => 1.0,
+            MISHeuristic::LightOnly => if light_pdf > 0.0 { 0.0 } else { 1.0 },
+            _ => self.weight(bsdf_pdf, light_pdf)
+        }
+    }
+
+    // Share of the strategy that picked a sample with density `pdf` when the other would pick it with `other`
+    fn weight(self, pdf: FloatT, other: FloatT) -> FloatT {
+        let (a, b) = match self {
+            MISHeuristic::Power => (pdf * pdf, other * other),
+            _ => (pdf, other)
+        };
+        if a + b > 0.0 { a / (a + b) } else { 0.0 }
+    }
+}
+
+/// A point on a light picked by [`Light::sample`].
+pub struct LightSample {
+    /// Unit direction from the shaded point towards the light.
+    pub wi: Vec3,
+    /// Point on the light, the end of the shadow ray.
+    pub target: Point3,
+    /// Radiance arriving along `wi`, or irradiance for delta lights.
+    pub li: Color,
+    /// Density `wi` was picked with per steradian, 1 for delta lights.
+    pub pdf: FloatT,
+    /// True if the light can only be reached along `wi`, so BSDF sampling can't find it.
+    pub delta: bool
+}
+
+/// Anything that lights the scene and can be sampled directly from a shaded point.
+pub trait Light : Send + Sync {
+    /// Picks a point on the light to illuminate `p` from, None if no light leaves towards `p`.
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<LightSample>;
+
+    /// Density with which [`Light::sample`] picks the direction of `r` from its origin, if the ray
+    /// reaches the light's emitting surface at `t`. Zero otherwise and for delta lights.
+    fn pdf(&self, r: &Ray, t: FloatT) -> FloatT;
+
+    /// Radiance and ray parameter where `r` reaches emitting surfaces that aren't part of the
+    /// world, if that happens before `t_max`. Lights made of scene geometry emit through their material.
+    fn emitted(&self, _r: &Ray, _t_max: FloatT) -> Option<(Color, FloatT)> {
+        None
+    }
+}
+
+/// A surface whose points can be sampled as seen from elsewhere, so it can become an area light.
+pub trait Shape : Hit {
+    /// Picks a point on the surface visible from `p`. Returns it with the surface's outward normal
+    /// there and the density per steradian of the direction from `p`.
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<(Point3, Vec3, FloatT)>;
+
+    /// Density with which [`Shape::sample`] picks the direction from `p` to the surface point `q`.
+    fn pdf(&self, p: Point3, q: Point3) -> FloatT;
+}
+
+/// A point light, or a spherical light when it has a radius. Either way it's invisible to camera
+/// rays that don't pass through the sphere and casts no shadows of its own.
+pub struct PointLight {
+    p: Point3,
+    c: Color,
+    l: FloatT,
+    r: FloatT
+}
+
+impl PointLight {
+    /// Creates a light at `point` with intensity `color` scaled by `lumonocity`. A positive `radius`
+    /// spreads the light over a sphere that emits the same total power.
+    pub fn new(point: Point3, color: Color, lumonocity: FloatT, radius: FloatT) -> PointLight {
+        PointLight {
+            p: point,
+            c: color,
+            l: lumonocity,
+            r: radius
+        }
+    }
+
+    // Radiance leaving the surface of a spherical light. Seen from afar it subtends a solid angle
+    // of πr²/d², so it lights a surface as brightly as the point light would.
+    fn radiance(&self) -> Color {
+        self.c * self.l / (PI * self.r * self.r)
+    }
+
+    // Ray parameter where `r` enters the sphere within `(t_min, t_max)`. Rays starting inside never hit it.
+    fn intersect(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> Option<FloatT> {
+        let oc = r.origin() - self.p;
+        let a = r.direction().length().powi(2);
+        let half_b = oc.dot(r.direction());
+        let c = oc.length().powi(2) - self.r * self.r;
+        let discriminant = half_b * half_b - a * c;
+        if c <= 0.0 || discriminant < 0.0 {
+            return None;
+        }
+        let t = (-half_b - discriminant.sqrt()) / a;
+        (t > t_min && t < t_max).then_some(t)
+    }
+}
+
+// Cosine of the half angle a sphere covers as seen from `p` and one minus it, None from inside it
+pub(crate) fn sphere_cone(center: Point3, radius: FloatT, p: Point3) -> Option<(FloatT, FloatT)> {
+    let dist2 = (center - p).length().powi(2);
+    let sin2 = radius * radius / dist2;
+    if sin2 >= 1.0 {
+        return None;
+    }
+    // Written this way it stays accurate for small, distant spheres
+    let cos_max = (1.0 - sin2).sqrt();
+    Some((cos_max, sin2 / (1.0 + cos_max)))
+}
+
+// Picks a direction from `p` uniformly within the cone a sphere covers. Returns it, the distance
+// to where it enters the sphere and its density.
+pub(crate) fn sample_sphere_cone(center: Point3, radius: FloatT, p: Point3,
+                                 u: (FloatT, FloatT)) -> Option<(Vec3, FloatT, FloatT)> {
+    let (cos_max, one_minus_cos) = sphere_cone(center, radius, p)?;
+    let axis = (center - p).normalized();
+    let (s, t) = axis.basis();
+    let v = sample_cone(u, cos_max);
+    let dir = v.x() * s + v.y() * t + v.z() * axis;
+
+    let to_center = (center - p).length();
+    let sin2 = (1.0 - v.z() * v.z()).max(0.0);
+    let dist = to_center * v.z() - (radius * radius - to_center * to_center * sin2).max(0.0).sqrt();
+    Some((dir, dist, 1.0 / (2.0 * PI * one_minus_cos)))
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<LightSample> {
+        if self.r > 0.0 {
+            let (wi, dist, pdf) = sample_sphere_cone(self.p, self.r, p, u)?;
+            return Some(LightSample { wi, target: p + dist * wi, li: self.radiance(), pdf, delta: false });
+        }
+
+        // Point lights fall off with the square of the distance
+        let to_light: Vec3 = self.p - p;
+        let dist = to_light.length();
+        Some(LightSample {
+            wi: to_light / dist,
+            target: self.p,
+            li: self.c * self.l / (dist * dist),
+            pdf: 1.0,
+            delta: true
+        })
+    }
+
+    fn pdf(&self, r: &Ray, t: FloatT) -> FloatT {
+        if self.r <= 0.0 || self.intersect(r, 0.001, FloatT::INFINITY) != Some(t) {
+            return 0.0;
+        }
+        match sphere_cone(self.p, self.r, r.origin()) {
+            Some((_, one_minus_cos)) => 1.0 / (2.0 * PI * one_minus_cos),
+            None => 0.0
+        }
+    }
+
+    fn emitted(&self, r: &Ray, t_max: FloatT) -> Option<(Color, FloatT)> {
+        if self.r <= 0.0 {
+            return None;
+        }
+        self.intersect(r, 0.001, t_max).map(|t| (self.radiance(), t))
+    }
+}
+
+/// A point light that only shines within a cone, fading out smoothly between an inner and an outer angle.
+pub struct SpotLight {
     p: Point3,
     c: Color,
     dir: Vec3,
-    l: FloatT
+    l: FloatT,
+    cos_inner: FloatT,
+    cos_outer: FloatT
 }
 
-impl Light {
-    pub fn new(point: Point3, color: Color, direction: Vec3, lumonocity: FloatT) -> Light {
-        Light {
+impl SpotLight {
+    /// Creates a spotlight at `point` shining along `direction` with intensity `color` scaled by
+    /// `lumonocity`. Full intensity reaches `inner` degrees from the axis and none beyond `outer`.
+    pub fn new(point: Point3, color: Color, direction: Vec3, lumonocity: FloatT, inner: FloatT,
+               outer: FloatT) -> SpotLight {
+        SpotLight {
             p: point,
             c: color,
-            dir: direction,
-            l: lumonocity
+            dir: direction.normalized(),
+            l: lumonocity,
+            cos_inner: inner.min(outer).to_radians().cos(),
+            cos_outer: outer.to_radians().cos()
+        }
+    }
+
+    // Fraction of the full intensity sent along the unit direction `w`, smoothstepped across the edge
+    fn falloff(&self, w: Vec3) -> FloatT {
+        let cos = w.dot(self.dir);
+        if cos >= self.cos_inner {
+            return 1.0;
+        }
+        if cos <= self.cos_outer {
+            return 0.0;
+        }
+        let x = (cos - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        x * x * (3.0 - 2.0 * x)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: Point3, _u: (FloatT, FloatT)) -> Option<LightSample> {
+        let to_light: Vec3 = self.p - p;
+        let dist = to_light.length();
+        let wi = to_light / dist;
+        let falloff = self.falloff((-1.0) * wi);
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample { wi, target: self.p, li: self.c * self.l * falloff / (dist * dist), pdf: 1.0, delta: true })
+    }
+
+    fn pdf(&self, _r: &Ray, _t: FloatT) -> FloatT {
+        0.0
+    }
+}
+
+// Distance to the end of shadow rays towards lights at infinity
+const DISTANT: FloatT = 1e8;
+
+/// Light from infinitely far away, like the sun, arriving from a single direction or from a
+/// small disc in the sky when it has an angular diameter.
+pub struct DirectionalLight {
+    dir: Vec3,
+    c: Color,
+    l: FloatT,
+    cos_max: FloatT
+}
+
+impl DirectionalLight {
+    /// Creates a light shining along `direction` whose irradiance on a surface facing it is `color`
+    /// scaled by `lumonocity`. `angle` is the diameter of its disc in the sky in degrees.
+    pub fn new(direction: Vec3, color: Color, lumonocity: FloatT, angle: FloatT) -> DirectionalLight {
+        DirectionalLight {
+            dir: direction.normalized(),
+            c: color,
+            l: lumonocity,
+            cos_max: (angle / 2.0).to_radians().cos()
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        self.cos_max >= 1.0
+    }
+
+    // Radiance of the disc, chosen so the irradiance of the whole disc matches the delta light's
+    fn radiance(&self) -> Color {
+        self.c * self.l / (PI * (1.0 - self.cos_max * self.cos_max))
+    }
+
+    fn pdf_cone(&self) -> FloatT {
+        1.0 / (2.0 * PI * (1.0 - self.cos_max))
+    }
+
+    // True if the unit direction `w` points into the sun's disc
+    fn in_disc(&self, w: Vec3) -> bool {
+        !self.is_delta() && ((-1.0) * w).dot(self.dir) >= self.cos_max
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<LightSample> {
+        let axis = (-1.0) * self.dir;
+        if self.is_delta() {
+            return Some(LightSample { wi: axis, target: p + DISTANT * axis, li: self.c * self.l, pdf: 1.0, delta: true });
+        }
+
+        let (s, t) = axis.basis();
+        let v = sample_cone(u, self.cos_max);
+        let wi = v.x() * s + v.y() * t + v.z() * axis;
+        Some(LightSample { wi, target: p + DISTANT * wi, li: self.radiance(), pdf: self.pdf_cone(), delta: false })
+    }
+
+    fn pdf(&self, r: &Ray, t: FloatT) -> FloatT {
+        if t.is_finite() || !self.in_disc(r.direction().normalized()) {
+            return 0.0;
+        }
+        self.pdf_cone()
+    }
+
+    fn emitted(&self, r: &Ray, t_max: FloatT) -> Option<(Color, FloatT)> {
+        // Only rays that escape the scene see the sun
+        if t_max.is_finite() || !self.in_disc(r.direction().normalized()) {
+            return None;
+        }
+        Some((self.radiance(), FloatT::INFINITY))
+    }
+}
+
+/// The environment as a light, seen by rays that escape the scene and sampled directly when it
+/// supports that.
+pub struct EnvironmentLight {
+    env: Arc<dyn Environment>
+}
+
+impl EnvironmentLight {
+    /// Creates a light for `env`.
+    pub fn new(env: Arc<dyn Environment>) -> EnvironmentLight {
+        EnvironmentLight {
+            env
+        }
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<LightSample> {
+        let (wi, pdf) = self.env.sample(u)?;
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample { wi, target: p + DISTANT * wi, li: self.env.radiance(wi), pdf, delta: false })
+    }
+
+    fn pdf(&self, r: &Ray, t: FloatT) -> FloatT {
+        if t.is_finite() {
+            return 0.0;
+        }
+        self.env.pdf(r.direction().normalized())
+    }
+
+    fn emitted(&self, r: &Ray, t_max: FloatT) -> Option<(Color, FloatT)> {
+        if t_max.is_finite() {
+            return None;
+        }
+        Some((self.env.radiance(r.direction().normalized()), FloatT::INFINITY))
+    }
+}
+
+/// Light given off by a shape in the world with an emissive material, sampled over its surface
+/// for soft shadows.
+pub struct AreaLight {
+    shape: Arc<dyn Shape>,
+    radiance: Color
+}
+
+impl AreaLight {
+    /// Creates a light for `shape`, whose material emits `radiance` from its front side.
+    pub fn new(shape: Arc<dyn Shape>, radiance: Color) -> AreaLight {
+        AreaLight {
+            shape,
+            radiance
+        }
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<LightSample> {
+        let (q, n, pdf) = self.shape.sample(p, u)?;
+        let wi = (q - p).normalized();
+        // Only the front side emits
+        if wi.dot(n) >= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample { wi, target: q, li: self.radiance, pdf, delta: false })
+    }
+
+    fn pdf(&self, r: &Ray, t: FloatT) -> FloatT {
+        // Only counts if the ray's first hit is this shape, which gives the same parameter
+        match self.shape.hit(r, 0.001, FloatT::INFINITY) {
+            (_, Some(rec)) if rec.t == t => self.shape.pdf(r.origin(), rec.p),
+            _ => 0.0
         }
     }
 }
 
+/// Direct lighting from a set of lights.
 pub trait LightHit {
-    fn get_color(&self, rec: &HitRecord, world: &World) -> Color;
+    /// Light from every light that `rec`'s material reflects back along `r_in`, found by sampling
+    /// each light once. Lights blocked by anything in `world` are left out and lights with an
+    /// area are weighted against BSDF sampling with `heuristic`.
+    fn get_color(&self, r_in: &Ray, rec: &HitRecord, world: &dyn Hit, sampler: &mut dyn Sampler,
+                 heuristic: MISHeuristic) -> Color;
+
+    /// Light emitted back along `r` by lights outside the world that it reaches before `t_max`.
+    /// `bsdf_pdf` is the density the BSDF picked the direction with, or None when light sampling
+    /// couldn't have found these lights, as for camera rays and delta lobes.
+    fn emitted(&self, r: &Ray, t_max: FloatT, bsdf_pdf: Option<FloatT>, heuristic: MISHeuristic) -> Color;
+
+    /// Summed density with which the lights would pick the direction of `r`, which first hits an
+    /// emitting surface at `t`.
+    fn pdf(&self, r: &Ray, t: FloatT) -> FloatT;
 }
 
-pub type Lights = Vec<Box<Light>>;
+/// All the lights in a scene.
+pub type Lights = Vec<Box<dyn Light>>;
 
 impl LightHit for Lights {
-    fn get_color(&self, rec: &HitRecord, world: &World) -> Color {
-        let mut origin: Point3 = rec.p;
+    fn get_color(&self, r_in: &Ray, rec: &HitRecord, world: &dyn Hit, sampler: &mut dyn Sampler,
+                 heuristic: MISHeuristic) -> Color {
         let mut color: Color = Color::default();
+        let wo = (-1.0) * r_in.direction().normalized();
 
         for l in self {
-            let dir: Vec3 = l.p - rec.p;
-            let r = Ray::new(origin, dir);
-            let len = dir.length();
-
-            for o in world {
-                if let (b, Some(trec)) = o.hit(&r, 0.0001, len) {
-                    return Color::default();
-                }
+            // Drawn even when unused so every light keeps the same sampler dimensions
+            let u = sampler.get_2d();
+            let Some(ls) = l.sample(rec.p, u) else {
+                continue;
+            };
+
+            // Skip the shadow ray when the surface wouldn't reflect this light anyway
+            let cos_theta = ls.wi.dot(rec.normal);
+            let f = rec.mat.eval(wo, ls.wi, rec);
+            if cos_theta <= 0.0 || f.near_zero() {
+                continue;
             }
-            color += l.c * l.l;
+
+            // Each light is shadowed on its own, the others still contribute
+            if world.occluded(rec.p, ls.target) {
+                continue;
+            }
+
+            let weight = if ls.delta { 1.0 } else { heuristic.light_weight(ls.pdf, rec.mat.pdf(wo, ls.wi, rec)) };
+            color += f * ls.li * cos_theta * weight / ls.pdf;
         }
         color
     }
+
+    fn emitted(&self, r: &Ray, t_max: FloatT, bsdf_pdf: Option<FloatT>, heuristic: MISHeuristic) -> Color {
+        let mut color: Color = Color::default();
+
+        // Lights outside the world don't block each other
+        for l in self {
+            let Some((le, t)) = l.emitted(r, t_max) else {
+                continue;
+            };
+            let weight = match bsdf_pdf {
+                Some(pdf) => heuristic.bsdf_weight(pdf, l.pdf(r, t)),
+                None => 1.0
+            };
+            color += le * weight;
+        }
+        color
+    }
+
+    fn pdf(&self, r: &Ray, t: FloatT) -> FloatT {
+        self.iter().map(|l| l.pdf(r, t)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+    use crate::rect::Rect;
+    use crate::material::{BSDF, Lambertian};
+    use crate::sampler::sample_unit_sphere;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    const DIRECTIONS: usize = 400_000;
+
+    fn material() -> Arc<dyn BSDF> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // Checks the density `light` samples directions from `p` with integrates to 1 over the sphere
+    // and matches what its pdf reports. `t` finds where a ray reaches the light's emitting surface.
+    fn check_pdf(light: &dyn Light, p: Point3, t: impl Fn(&Ray) -> FloatT) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let sum: FloatT = (0..DIRECTIONS).map(|_| {
+            let r = Ray::new(p, sample_unit_sphere((rng.gen(), rng.gen())));
+            light.pdf(&r, t(&r))
+        }).sum();
+        let integral = 4.0 * PI * sum / DIRECTIONS as FloatT;
+        assert!((integral - 1.0).abs() < 0.03, "pdf integrates to {}", integral);
+
+        for _ in 0..100 {
+            let ls = light.sample(p, (rng.gen(), rng.gen())).unwrap();
+            let r = Ray::new(p, ls.wi);
+            let pdf = light.pdf(&r, t(&r));
+            assert!((pdf - ls.pdf).abs() < 1e-6 * ls.pdf, "sampled with {} but pdf gives {}", ls.pdf, pdf);
+        }
+    }
+
+    // Where `r` reaches the light's own surface outside the world
+    fn emitted_t(light: &dyn Light) -> impl Fn(&Ray) -> FloatT + '_ {
+        move |r| light.emitted(r, FloatT::INFINITY).map_or(FloatT::INFINITY, |(_, t)| t)
+    }
+
+    // Where `r` first hits `shape`
+    fn hit_t(shape: &dyn Shape) -> impl Fn(&Ray) -> FloatT + '_ {
+        move |r| shape.hit(r, 0.001, FloatT::INFINITY).1.map_or(FloatT::INFINITY, |rec| rec.t)
+    }
+
+    #[test]
+    fn spherical_point_light_pdf_integrates_to_one() {
+        let light = PointLight::new(Point3::new(0.0, 1.5, 0.0), Color::new(1.0, 1.0, 1.0), 1.0, 1.0);
+        check_pdf(&light, Point3::new(0.0, 0.0, 0.0), emitted_t(&light));
+    }
+
+    #[test]
+    fn sphere_area_light_pdf_integrates_to_one() {
+        let sphere = Arc::new(Sphere::new(Point3::new(1.0, 1.0, 0.0), 1.0, material()));
+        let light = AreaLight::new(sphere.clone(), Color::new(1.0, 1.0, 1.0));
+        check_pdf(&light, Point3::new(0.0, -0.5, 0.5), hit_t(sphere.as_ref()));
+    }
+
+    #[test]
+    fn rect_area_light_pdf_integrates_to_one() {
+        // Facing down onto the point below it
+        let rect = Arc::new(Rect::new(Point3::new(-1.0, 1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0),
+                                      material()));
+        let light = AreaLight::new(rect.clone(), Color::new(1.0, 1.0, 1.0));
+        check_pdf(&light, Point3::new(0.3, 0.0, 0.2), hit_t(rect.as_ref()));
+    }
+
+    #[test]
+    fn sun_disc_pdf_integrates_to_one() {
+        let light = DirectionalLight::new(Vec3::new(1.0, -2.0, 0.5), Color::new(1.0, 1.0, 1.0), 1.0, 60.0);
+        check_pdf(&light, Point3::new(0.0, 0.0, 0.0), emitted_t(&light));
+    }
+
+    #[test]
+    fn sun_disc_gives_the_requested_irradiance() {
+        let dir = Vec3::new(1.0, -2.0, 0.5);
+        let irradiance = Color::new(1.0, 0.5, 0.25) * 3.0;
+        let n = (-1.0) * dir.normalized();
+        for angle in [0.0, 0.5, 30.0] {
+            let light = DirectionalLight::new(dir, Color::new(1.0, 0.5, 0.25), 3.0, angle);
+            let mut rng = StdRng::seed_from_u64(3);
+            let samples = 10_000;
+            let mut e = Color::default();
+            for _ in 0..samples {
+                let ls = light.sample(Point3::new(0.0, 0.0, 0.0), (rng.gen(), rng.gen())).unwrap();
+                e += ls.li * ls.wi.dot(n) / ls.pdf;
+            }
+            e /= samples as FloatT;
+            assert!((e - irradiance).length() < 1e-3 * irradiance.length(), "{} degrees gave {}", angle, e);
+        }
+    }
 }
 
-//pub trait Hit : Send + Sync {
-//    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> Option<HitRecord>;
-//}
-//
-//pub type World = Vec<Box<dyn Hit>>;
-//
-//impl Hit for World {
-//    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> Option<HitRecord> {
-//        let mut tmp_rec = None;
-//        let mut closest = t_max;
-//
-//        // Find the closest object that the ray intersects
-//        for object in self {
-//            if let Some(rec) = object.hit(r, t_min, closest) {
-//                closest = rec.t;
-//                tmp_rec = Some(rec);
-//            }
-//        }
-//        tmp_rec
-//    }
-//}
\ No newline at end of file
@@ -1,258 +1,119 @@
-mod vec;
-mod ray;
-mod hit;
-mod sphere;
-mod camera;
-mod material;
-mod light; 
-mod aabb;
-mod object;
-mod bvh;
+mod cli;
 
-use vec::{Vec3, Point3, Color, FloatT};
-use ray::Ray;
-use hit::{Hit, World};
-use sphere::Sphere;
-use camera::Camera;
-use material::{Lambertian, Metal, Dielectric};
-use light::{Light, Lights, LightHit};
-use aabb::*;
+use raytracing::vec::FloatT;
+use raytracing::bvh::BVHTree;
+use raytracing::scene::Scene;
+use raytracing::render::{Renderer, AuxChannel};
+use raytracing::output::{self, OutputOptions};
+use raytracing::tonemap::ToneMap;
+use cli::{Options, CliError};
 
-use std::io::{stderr, Write};
-use rand::{Rng, thread_rng, random};
-use std::sync::Arc;
-use rayon::prelude::*;
-use std::fs::File;
+use std::path::Path;
+use std::process;
+use std::env;
 
+// https://misterdanb.github.io/raytracinginrust/#outputanimage/theppmimageformat
+fn main() {
+    let opts = match Options::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(CliError::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
 
-const SUN_LUMINOCITY: FloatT = 1.0;
-const LIGHT_LUMONICITY: FloatT = 1.0;
-
-fn print_type_of<T>(_: &T) {
-    println!("{}", std::any::type_name::<T>())
-}
-
-fn ray_color(r: &Ray, world: &World, lights: &Lights, depth: u64) -> Color {
-    // Maximum ray-bounce depth has been reached
-    if depth <= 0 {
-        return Color::default();
+    if let Some(threads) = opts.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().unwrap();
     }
 
-    if let (h, Some(rec)) = world.hit(r, 0.001, FloatT::INFINITY)  {
-        if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-            attenuation * ray_color(&scattered, world, lights, depth - 1)
-        } else {
-            Color::default()
+    // Scene
+    let scene_file = opts.scene_path();
+    let scene = match Scene::load(&scene_file) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("{}: {}", scene_file, e);
+            process::exit(1);
         }
-    } else {
-        //Color::default()
-        let unit_dir = r.direction().normalized();
-        let t = 0.5 * (unit_dir.y() + 1.0);
-        ((1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)) * SUN_LUMINOCITY
-    } 
-
-    // Two scattering methods       
-    // Lambertian scattering
-    // let target = rec.p + rec.normal + Vec3::random_in_unit_sphere().normalized();
-    // Hemisphere scattering
-    // let target = rec.p + Vec3::random_in_hemisphere(rec.normal);
-}
-
-fn random_scene() -> (World, Lights) {
-    let mut rng = rand::thread_rng();
-    let mut world = World::new();
-    let mut lights = Lights::new(); 
-
-    let ground_mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    let ground_sphere = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat);
-
-    world.push(Box::new(ground_sphere));
-
-    for a in -11..=11 {
-        for b in -11..=11 {
-            let choose_mat: f64 = rng.gen();
-            let center = Point3::new((a as f64) + rng.gen_range(0.0..0.9),
-                                     0.2,
-                                     (b as f64) + rng.gen_range(0.0..0.9));
-
-            if choose_mat < 0.8 {
-                // Diffuse
-                let albedo = Color::random(0.0..1.0) * Color::random(0.0..1.0);
-                let sphere_mat = Arc::new(Lambertian::new(albedo));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere));
-            } else if choose_mat < 0.95 {
-                // Metal
-                let albedo = Color::random(0.4..1.0);
-                let fuzz = rng.gen_range(0.0..0.5);
-                let sphere_mat = Arc::new(Metal::new(albedo, fuzz));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere));
-            } else {
-                // Glass / Dielectrics
-                let sphere_mat = Arc::new(Dielectric::new(1.5));
-                //let sphere_mat = Arc::new(Dielectric::new(rng.gen_range(0.01..20.0)));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere));
-            }
+    };
+
+    // Command line settings take precedence over the scene's, a lone width or height keeps the aspect ratio
+    let aspect_ratio = (scene.image.width as FloatT) / (scene.image.height as FloatT);
+    let (image_width, image_height) = match (opts.width, opts.height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, ((w as FloatT) / aspect_ratio).max(1.0) as u64),
+        (None, Some(h)) => (((h as FloatT) * aspect_ratio).max(1.0) as u64, h),
+        (None, None) => (scene.image.width, scene.image.height)
+    };
+    let samples_per_pixel = opts.samples_per_pixel.unwrap_or(scene.image.samples_per_pixel);
+    let max_depth = opts.max_depth.unwrap_or(scene.image.max_depth);
+    let world = BVHTree::new(scene.world);
+    let mut cam = scene.camera.build((image_width as FloatT) / (image_height as FloatT));
+    if let Some((s, t)) = scene.camera.focus_point {
+        // The camera's v axis points up
+        if !cam.focus_on(&world, s, 1.0 - t) {
+            eprintln!("{}: nothing to focus on at focus_pixel, keeping the focus distance", scene_file);
         }
     }
 
-    let mat1 = Arc::new(Dielectric::new(1.5));
-    let mat2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    let mat3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-
-    let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1);
-    let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2);
-    let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3);
-
-    let light1 = Light::new(Point3::new(0.0, 15.0, 0.0), Color::new(1.0, 1.0, 1.0), Vec3::new(0.0, -1.0, 0.0), LIGHT_LUMONICITY);
-
-    world.push(Box::new(sphere1));
-    world.push(Box::new(sphere2));
-    world.push(Box::new(sphere3));
-
-    lights.push(Box::new(light1));
-
-    (world, lights)
-}
-
-fn debug_scene() -> (World, Lights) {
-    let mut world = World::new();
-    let mut lights = Lights::new(); 
-
-    let mat_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
-    let mat_center = Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
-    let mat_left = Arc::new(Dielectric::new(1.5));
-    let mat_left_inner = Arc::new(Dielectric::new(1.5));
-    let mat_right = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
-
-    let sphere_ground = Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, mat_ground);
-    let sphere_center = Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, mat_center);
-    let sphere_left = Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, mat_left);
-    let sphere_left_inner = Sphere::new(Point3::new(-1.0, 0.0, -1.0), -0.45, mat_left_inner);
-    let sphere_right = Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, mat_right);
-
-    let light1 = Light::new(Point3::new(0.0, 15.0, 0.0), Color::new(1.0, 1.0, 1.0), Vec3::new(0.0, -1.0, 0.0), LIGHT_LUMONICITY);
-
-    world.push(Box::new(sphere_ground));
-    world.push(Box::new(sphere_center));
-    world.push(Box::new(sphere_left));
-    world.push(Box::new(sphere_left_inner));
-    world.push(Box::new(sphere_right));   
-
-    lights.push(Box::new(light1));
-
-    (world, lights)
-}
-
-// https://misterdanb.github.io/raytracinginrust/#outputanimage/theppmimageformat
-fn main() {
-    
-    // Image
-    const ASPECT_RATIO: FloatT = 16.0 / 9.0;
-    let mut IMAGE_WIDTH: u64 = 768;
-    let mut IMAGE_HEIGHT: u64 = ((IMAGE_WIDTH as FloatT) / ASPECT_RATIO) as u64;
-    let mut SAMPLES_PER_PIXEL: u64 = 100;
-    let mut MAX_DEPTH: u64 = 16;
-
-    #[derive(Debug, PartialEq, Eq)]
-    pub enum Mode {
-        FAST_DEBUG,
-        DEBUG,
-        RUN
+    let mut renderer = Renderer::new(image_width, image_height);
+    renderer.samples_per_pixel = samples_per_pixel;
+    renderer.max_depth = max_depth;
+    renderer.seed = opts.seed.unwrap_or(0);
+    renderer.progress = true;
+    renderer.adaptive = opts.adaptive;
+    if let Some(depth) = opts.diffuse_depth {
+        renderer.diffuse_depth = depth;
     }
-
-    let (mut world, mut lights) = (World::new(), Lights::new());    
-    //const runMode: Mode = Mode::DEBUG;
-    const runMode: Mode = Mode::FAST_DEBUG;
-    //const runMode: Mode = Mode::DEBUG;
-
-
-    if runMode == Mode::FAST_DEBUG {
-        (world, lights) = debug_scene();
-        IMAGE_WIDTH = 1200;
-        IMAGE_HEIGHT = ((IMAGE_WIDTH as FloatT) / ASPECT_RATIO) as u64;
-        SAMPLES_PER_PIXEL = 100;
-        MAX_DEPTH = 9;
-    } else if runMode == Mode::DEBUG {
-        (world, lights) = random_scene();
-        IMAGE_WIDTH = 1200;
-        IMAGE_HEIGHT = ((IMAGE_WIDTH as FloatT) / ASPECT_RATIO) as u64;
-        SAMPLES_PER_PIXEL = 32;
-        MAX_DEPTH = 16;
-    } else {
-        (world, lights) = random_scene();
+    if let Some(depth) = opts.specular_depth {
+        renderer.specular_depth = depth;
+    }
+    if let Some(depth) = opts.transmission_depth {
+        renderer.transmission_depth = depth;
+    }
+    if let Some(depth) = opts.roulette_depth {
+        renderer.roulette_depth = depth;
+    }
+    if let Some(mis) = opts.mis {
+        renderer.mis = mis;
+    }
+    renderer.filter = opts.filter;
+    // The heatmap needs sample counts even when the output doesn't
+    let extra_counts = opts.heatmap.is_some() && !opts.aux_channels.contains(&AuxChannel::SampleCount);
+    renderer.aux_channels = opts.aux_channels;
+    if extra_counts {
+        renderer.aux_channels.push(AuxChannel::SampleCount);
+    }
+    if let Some(sampler) = opts.sampler {
+        renderer.sampler = sampler;
+    }
+    if let Some(tile_size) = opts.tile_size {
+        renderer.tile_size = tile_size;
+    }
+    if let Some(tile_order) = opts.tile_order {
+        renderer.tile_order = tile_order;
     }
 
-    // Camera
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-    let fov = 20.0;
-
-    let cam = Camera::new(lookfrom,
-                          lookat,
-                          vup,
-                          fov,
-                          ASPECT_RATIO,
-                          aperture,
-                          dist_to_focus);
-
-    let filename = "./image.ppm";
-    let mut buffer = File::create(filename).unwrap();
-    
-    writeln!(&mut buffer, "P3");
-    writeln!(&mut buffer, "{} {}", IMAGE_WIDTH, IMAGE_HEIGHT);
-    writeln!(&mut buffer, "255");
-
-
-    //let mut count = 0;
-    for j in (0..IMAGE_HEIGHT).rev()
-    {
-        eprint!("\r {:3}/{} scanlines", IMAGE_HEIGHT - j - 1, IMAGE_HEIGHT);
-        stderr().flush().unwrap();
-
-        let scanline: Vec<Color> = (0..IMAGE_WIDTH).into_par_iter().map(|i| {
-            let mut pixel_color = Color::default();
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let mut rng = thread_rng();
-                let random_u: FloatT = rng.gen();
-                let random_v: FloatT = rng.gen();
+    let mut image = renderer.render(&world, &scene.lights, cam.as_ref());
+    let heatmap = opts.heatmap.as_ref().map(|_| image.heatmap("SampleCount").unwrap());
+    if extra_counts {
+        image.remove_channel("SampleCount");
+    }
 
-                let u = ((i as FloatT) + random_u) / ((IMAGE_WIDTH - 1) as FloatT);
-                let v = ((j as FloatT) + random_v) / ((IMAGE_HEIGHT - 1) as FloatT);
-    
-                let r = cam.get_ray(u, v);
-                pixel_color += ray_color(&r, &world, &lights, MAX_DEPTH);
-            }
-            pixel_color
-        }).collect();
+    if let Err(e) = output::save(&image, Path::new(&opts.output), &opts.output_options) {
+        eprintln!("{}: {}", opts.output, e);
+        process::exit(1);
+    }
 
-        for pixel_color in scanline {
-            writeln!(&mut buffer, "{}", pixel_color.format_color(SAMPLES_PER_PIXEL));
+    if let (Some(path), Some(heatmap)) = (&opts.heatmap, heatmap) {
+        // The ramp is already in display colors
+        let options = OutputOptions { tone_map: ToneMap::identity(), ..OutputOptions::default() };
+        if let Err(e) = output::save(&heatmap, Path::new(path), &options) {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
         }
     }
-    eprintln!("Done");
 }
-
-//// Python
-//def main():
-//    print("Hello World")
-//
-//// C++
-//#include <iostream>
-//void main()
-//{
-//    stdd::cout << "Hello World" << std::endl;
-//}
-//
-////Rust
-//fn main() {
-//    eprintln!("Hello World");
-//}
\ No newline at end of file
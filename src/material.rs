@@ -1,36 +1,109 @@
 use super::hit::{HitRecord};
-use super::ray::{Ray};
 use super::vec::{Vec3, Color, FloatT};
-use rand::{Rng, thread_rng};
+use super::sampler::{Sampler, sample_unit_sphere, sample_unit_ball};
 
-pub trait Scatter : Send + Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+use std::f64::consts::PI;
+
+/// Kind of scattering event, used to limit path depth per kind.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    /// Scattered in a random direction over the hemisphere.
+    Diffuse,
+    /// Mirror-like reflection, possibly blurred.
+    Specular,
+    /// Refracted through the surface.
+    Transmission
+}
+
+/// A direction picked by [`BSDF::sample`].
+pub struct BSDFSample {
+    /// Unit direction the light arrives from.
+    pub wi: Vec3,
+    /// Factor for the path throughput, the BSDF times the cosine divided by the pdf.
+    pub weight: Color,
+    /// Density `wi` was picked with per steradian, or the probability of picking it for delta lobes.
+    pub pdf: FloatT,
+    /// Kind of scattering that was sampled.
+    pub lobe: Lobe,
+    /// True if `wi` is the only direction this lobe scatters into, so it can't be found by light sampling.
+    pub delta: bool
+}
+
+/// How a surface scatters light. Directions are unit vectors pointing away from the surface:
+/// `wo` towards the viewer and `wi` towards the incoming light.
+pub trait BSDF : Send + Sync {
+    /// Picks an incoming direction for light leaving along `wo`, or None if the path is absorbed.
+    ///
+    /// Random decisions use the next dimensions of `sampler`, a material must always draw the
+    /// same number of them.
+    fn sample(&self, wo: Vec3, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<BSDFSample>;
+
+    /// Fraction of the light arriving from `wi` that leaves along `wo`, per steradian. Zero for delta lobes.
+    fn eval(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color;
+
+    /// Density with which [`BSDF::sample`] picks `wi` for `wo`. Zero for delta lobes.
+    fn pdf(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> FloatT;
+
+    /// True if the material only scatters into single directions, so `eval` and `pdf` are always zero.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// Radiance the surface gives off by itself at `rec`, black for everything but lights.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
 }
 
 
+/// A reflective metal, `fuzz` randomly perturbs the reflection.
 pub struct Metal {
     albedo: Color,
     fuzz: FloatT
 }
 
+/// An ideal diffuse surface.
 pub struct Lambertian {
     albedo: Color
 }
 
+/// A transparent material like glass or water that both reflects and refracts.
 pub struct Dielectric {
     ir: FloatT
 }
 
+/// A surface that glows evenly from its front side and reflects nothing.
+pub struct DiffuseLight {
+    emit: Color
+}
+
 impl Metal {
+    /// Creates a metal with albedo `a` and fuzz `f`.
     pub fn new(a: Color, f: FloatT) -> Metal {
         Metal {
             albedo: a,
             fuzz: f
         }
     }
+
+    // Density of `wi` when the mirror direction `r` is offset by a uniform point in a ball of
+    // radius fuzz: the volume of the ball along `wi`, weighted by distance squared
+    fn fuzz_pdf(&self, r: Vec3, wi: Vec3) -> FloatT {
+        let c = wi.dot(r);
+        let disc = c * c - (1.0 - self.fuzz * self.fuzz);
+        if disc <= 0.0 {
+            return 0.0;
+        }
+        let (t0, t1) = (c - disc.sqrt(), c + disc.sqrt());
+        if t1 <= 0.0 {
+            return 0.0;
+        }
+        (t1.powi(3) - t0.max(0.0).powi(3)) / (4.0 * PI * self.fuzz.powi(3))
+    }
 }
 
 impl Lambertian {
+    /// Creates a diffuse surface with albedo `a`.
     pub fn new(a: Color) -> Lambertian {
         Lambertian {
             albedo: a
@@ -39,6 +112,7 @@ impl Lambertian {
 }
 
 impl Dielectric {
+    /// Creates a dielectric with the given index of refraction.
     pub fn new(index_of_refraction: FloatT) -> Dielectric {
         Dielectric {
             ir: index_of_refraction
@@ -51,61 +125,158 @@ impl Dielectric {
     }
 }
 
-impl Scatter for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let reflected = r_in.direction().reflect(rec.normal).normalized();
-        let scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere());
+impl DiffuseLight {
+    /// Creates a light emitting `color` scaled by `intensity`.
+    pub fn new(color: Color, intensity: FloatT) -> DiffuseLight {
+        DiffuseLight {
+            emit: color * intensity
+        }
+    }
 
-        if scattered.direction().dot(rec.normal) > 0.0 {
-            Some((self.albedo, scattered))
-        } else {
-            None
+    /// Radiance given off by the front side.
+    pub fn radiance(&self) -> Color {
+        self.emit
+    }
+}
+
+impl BSDF for Metal {
+    fn sample(&self, wo: Vec3, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<BSDFSample> {
+        let n = rec.normal;
+        let reflected = ((-1.0) * wo).reflect(n);
+        let offset = sample_unit_ball(sampler.get_2d(), sampler.get_1d());
+
+        if self.fuzz <= 0.0 {
+            return Some(BSDFSample { wi: reflected, weight: self.albedo, pdf: 1.0, lobe: Lobe::Specular, delta: true });
         }
+
+        // Reflections pushed below the surface are absorbed
+        let wi = (reflected + self.fuzz * offset).normalized();
+        if wi.dot(n) <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample { wi, weight: self.albedo, pdf: self.fuzz_pdf(reflected, wi), lobe: Lobe::Specular, delta: false })
+    }
+
+    fn eval(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color {
+        // Chosen so sampling weighs every reflection by the albedo
+        let cos_i = wi.dot(rec.normal);
+        if self.fuzz <= 0.0 || cos_i <= 0.0 {
+            return Color::default();
+        }
+        self.albedo * self.pdf(wo, wi, rec) / cos_i
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3, rec: &HitRecord) -> FloatT {
+        let n = rec.normal;
+        if self.fuzz <= 0.0 || wi.dot(n) <= 0.0 {
+            return 0.0;
+        }
+        self.fuzz_pdf(((-1.0) * wo).reflect(n), wi)
+    }
+
+    fn is_delta(&self) -> bool {
+        self.fuzz <= 0.0
     }
 }
 
-impl Scatter for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_in_unit_sphere().normalized();
+impl BSDF for Lambertian {
+    fn sample(&self, _wo: Vec3, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<BSDFSample> {
+        // A random point on the unit sphere around the normal's tip gives a cosine distribution
+        let mut scatter_direction = rec.normal + sample_unit_sphere(sampler.get_2d());
 
-        // If our random point on unit sphere is equal to our normal 
+        // If our random point on unit sphere is equal to our normal
         // it's possible scatter_direction is NaN/INF - this prevents that degenerate case
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
-    
-        let scattered = Ray::new(rec.p, scatter_direction);
-        Some((self.albedo, scattered))
+
+        let wi = scatter_direction.normalized();
+        Some(BSDFSample {
+            wi,
+            weight: self.albedo,
+            pdf: wi.dot(rec.normal).max(0.0) / PI,
+            lobe: Lobe::Diffuse,
+            delta: false
+        })
+    }
+
+    fn eval(&self, _wo: Vec3, wi: Vec3, rec: &HitRecord) -> Color {
+        if wi.dot(rec.normal) > 0.0 {
+            self.albedo / PI
+        } else {
+            Color::default()
+        }
+    }
+
+    fn pdf(&self, _wo: Vec3, wi: Vec3, rec: &HitRecord) -> FloatT {
+        wi.dot(rec.normal).max(0.0) / PI
     }
 }
 
-impl Scatter for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+impl BSDF for Dielectric {
+    fn sample(&self, wo: Vec3, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<BSDFSample> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
             self.ir
         };
 
-        let unit_direction = r_in.direction().normalized();
+        let unit_direction = (-1.0) * wo;
 
-        let cos_theta = ((-1.0) * unit_direction).dot(rec.normal).min(1.0);
+        let cos_theta = wo.dot(rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
 
-        let mut rng = rand::thread_rng();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let will_reflect = rng.gen::<f64>() < Self::reflectance(cos_theta, refraction_ratio);
+        let reflectance = if cannot_refract { 1.0 } else { Self::reflectance(cos_theta, refraction_ratio) };
+        let will_reflect = sampler.get_1d() < reflectance;
 
-        let direction = if cannot_refract || will_reflect {
-            unit_direction.reflect(rec.normal)
+        // The choice between reflection and refraction cancels the Fresnel weight
+        let (wi, pdf, lobe) = if will_reflect {
+            (unit_direction.reflect(rec.normal), reflectance, Lobe::Specular)
         } else {
-            unit_direction.refract(rec.normal, refraction_ratio)
+            (unit_direction.refract(rec.normal, refraction_ratio), 1.0 - reflectance, Lobe::Transmission)
         };
 
-        let scattered = Ray::new(rec.p, direction);
+        Some(BSDFSample {
+            wi,
+            weight: Color::new(1.0, 1.0, 1.0),
+            pdf,
+            lobe,
+            delta: true
+        })
+    }
 
-        Some((Color::new(1.0, 1.0, 1.0), scattered))
+    fn eval(&self, _wo: Vec3, _wi: Vec3, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    fn pdf(&self, _wo: Vec3, _wi: Vec3, _rec: &HitRecord) -> FloatT {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
     }
 }
 
+impl BSDF for DiffuseLight {
+    fn sample(&self, _wo: Vec3, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<BSDFSample> {
+        None
+    }
+
+    fn eval(&self, _wo: Vec3, _wi: Vec3, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
 
+    fn pdf(&self, _wo: Vec3, _wi: Vec3, _rec: &HitRecord) -> FloatT {
+        0.0
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit
+        } else {
+            Color::default()
+        }
+    }
+}
@@ -1,5 +0,0 @@
-use super::aabb::AABB;
-
-pub trait Object : Send + Sync {
-    fn hit(&self) -> AABB;
-}
\ No newline at end of file
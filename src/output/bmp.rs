@@ -0,0 +1,56 @@
+use super::ImageWriter;
+use crate::image::Image;
+use crate::tonemap::ToneMap;
+
+use std::io::{self, Write};
+
+const HEADER_SIZE: u32 = 14 + 40;
+
+/// Uncompressed 24-bit Windows bitmap.
+pub struct BMPWriter {
+    tone_map: ToneMap
+}
+
+impl BMPWriter {
+    /// Creates a writer that converts colors with `tone_map`.
+    pub fn new(tone_map: ToneMap) -> BMPWriter {
+        BMPWriter { tone_map }
+    }
+}
+
+impl ImageWriter for BMPWriter {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        // Rows are padded to a multiple of 4 bytes
+        let row_size = (3 * image.width() as u32).div_ceil(4) * 4;
+        let data_size = row_size * image.height() as u32;
+
+        // File header
+        out.write_all(b"BM")?;
+        out.write_all(&(HEADER_SIZE + data_size).to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&HEADER_SIZE.to_le_bytes())?;
+
+        // BITMAPINFOHEADER, a positive height means rows are stored bottom to top
+        out.write_all(&40u32.to_le_bytes())?;
+        out.write_all(&(image.width() as i32).to_le_bytes())?;
+        out.write_all(&(image.height() as i32).to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&24u16.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&data_size.to_le_bytes())?;
+        out.write_all(&2835i32.to_le_bytes())?;
+        out.write_all(&2835i32.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        let mut row = vec![0u8; row_size as usize];
+        for y in (0..image.height()).rev() {
+            for x in 0..image.width() {
+                let [r, g, b] = self.tone_map.quantize(image.get(x, y));
+                row[3 * x..3 * x + 3].copy_from_slice(&[b, g, r]);
+            }
+            out.write_all(&row)?;
+        }
+        Ok(())
+    }
+}
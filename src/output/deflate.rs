@@ -0,0 +1,595 @@
+//! A small DEFLATE (RFC 1951) encoder with zlib (RFC 1950) framing, used by the PNG and EXR writers.
+//! Matches are found with hash chains over a 32K window and every block gets its own
+//! length-limited Huffman codes.
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: usize = 15;
+// How many earlier positions with the same hash are tried for each match
+const MAX_CHAIN: usize = 128;
+// Symbols per block before new Huffman codes are built
+const BLOCK_TOKENS: usize = 1 << 16;
+
+const END_OF_BLOCK: usize = 256;
+
+// Base lengths and extra bits for length codes 257..=285
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+                                35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+                                3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+// Base distances and extra bits for distance codes 0..=29
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+                              257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+                              8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+                              7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Order code length code lengths are stored in the block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, dist: u16 }
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    n: u32
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> BitWriter {
+        BitWriter { out, acc: 0, n: 0 }
+    }
+
+    // Writes the low `n` bits of `bits`, least significant first
+    fn bits(&mut self, bits: u32, n: u32) {
+        self.acc |= (bits as u64) << self.n;
+        self.n += n;
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    // Huffman codes are stored most significant bit first
+    fn code(&mut self, code: u16, len: u8) {
+        let reversed = code.reverse_bits() >> (16 - len as u32);
+        self.bits(reversed as u32, len as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.n > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// CRC-32 as used by PNG and gzip.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
+
+/// Continues a CRC-32 computed over earlier bytes.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        table
+    });
+
+    let mut c = !crc;
+    for &b in bytes {
+        c = table[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the most bytes that can be summed before b could overflow
+    for chunk in bytes.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+/// Compresses `data` into a zlib stream.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // CMF: deflate with a 32K window, FLG: default level with the check bits making CMF*256+FLG a multiple of 31
+    let mut out = vec![0x78, 0x9c];
+    out = deflate_into(data, out);
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Compresses `data` into a raw deflate stream.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    deflate_into(data, Vec::new())
+}
+
+fn deflate_into(data: &[u8], out: Vec<u8>) -> Vec<u8> {
+    let tokens = lz77(data);
+    let mut w = BitWriter::new(out);
+
+    if tokens.is_empty() {
+        // A single empty block with the fixed codes
+        w.bits(1, 1);
+        w.bits(1, 2);
+        w.bits(0, 7);
+        return w.finish();
+    }
+
+    let blocks: Vec<&[Token]> = tokens.chunks(BLOCK_TOKENS).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        write_block(&mut w, block, i + 1 == blocks.len());
+    }
+    w.finish()
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn lz77(data: &[u8]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let insert = |i: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(data, i);
+            prev[i % WINDOW_SIZE] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_len = 0;
+        let mut best_dist = 0;
+
+        if i + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(data, i)];
+            let mut chain = 0;
+
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let len = data[candidate..].iter().zip(&data[i..i + max_len]).take_while(|(a, b)| a == b).count();
+                if len > best_len {
+                    best_len = len;
+                    best_dist = i - candidate;
+                    if len == max_len {
+                        break;
+                    }
+                }
+
+                let next = prev[candidate % WINDOW_SIZE];
+                // Stop once the chain wraps into entries overwritten by newer positions
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            tokens.push(Token::Match { len: best_len as u16, dist: best_dist as u16 });
+            for j in i..i + best_len {
+                insert(j, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            tokens.push(Token::Literal(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    tokens
+}
+
+fn length_code(len: u16) -> usize {
+    LENGTH_BASE.iter().rposition(|&b| b <= len).unwrap()
+}
+
+fn dist_code(dist: u16) -> usize {
+    DIST_BASE.iter().rposition(|&b| b <= dist).unwrap()
+}
+
+// Huffman code lengths no longer than `limit` bits, using the package-merge algorithm
+fn code_lengths(freqs: &[u32], limit: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; freqs.len()];
+    let mut leaves: Vec<(u64, usize)> = freqs.iter().enumerate()
+        .filter(|(_, &f)| f > 0)
+        .map(|(s, &f)| (f as u64, s))
+        .collect();
+
+    match leaves.len() {
+        0 => return lengths,
+        1 => {
+            lengths[leaves[0].1] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+    leaves.sort();
+
+    // Each item is a weight and the leaves it contains
+    let leaf_items: Vec<(u64, Vec<usize>)> = leaves.iter().map(|&(f, s)| (f, vec![s])).collect();
+    let mut items = leaf_items.clone();
+    for _ in 1..limit {
+        let packages: Vec<(u64, Vec<usize>)> = items.chunks_exact(2).map(|pair| {
+            let mut symbols = pair[0].1.clone();
+            symbols.extend_from_slice(&pair[1].1);
+            (pair[0].0 + pair[1].0, symbols)
+        }).collect();
+
+        let mut merged = Vec::with_capacity(leaf_items.len() + packages.len());
+        let (mut a, mut b) = (leaf_items.iter().peekable(), packages.into_iter().peekable());
+        loop {
+            match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) if x.0 <= y.0 => merged.push(a.next().unwrap().clone()),
+                (_, Some(_)) => merged.push(b.next().unwrap()),
+                (Some(_), None) => merged.push(a.next().unwrap().clone()),
+                (None, None) => break
+            }
+        }
+        items = merged;
+    }
+
+    for (_, symbols) in items.iter().take(2 * leaves.len() - 2) {
+        for &s in symbols {
+            lengths[s] += 1;
+        }
+    }
+    lengths
+}
+
+// Canonical Huffman codes for the given code lengths
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let max = *lengths.iter().max().unwrap_or(&0) as usize;
+    let mut count = vec![0u16; max + 1];
+    for &l in lengths {
+        if l > 0 {
+            count[l as usize] += 1;
+        }
+    }
+
+    let mut next = vec![0u16; max + 2];
+    let mut code = 0u16;
+    for bits in 1..=max {
+        code = (code + count[bits - 1]) << 1;
+        next[bits] = code;
+    }
+
+    lengths.iter().map(|&l| {
+        if l == 0 {
+            0
+        } else {
+            let c = next[l as usize];
+            next[l as usize] += 1;
+            c
+        }
+    }).collect()
+}
+
+// Run-length encodes code lengths with the repeat symbols 16, 17 and 18
+fn rle_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let l = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&x| x == l).count();
+
+        if l == 0 && run >= 3 {
+            let n = run.min(138);
+            if n >= 11 {
+                out.push((18, (n - 11) as u8));
+            } else {
+                out.push((17, (n - 3) as u8));
+            }
+            i += n;
+        } else if l != 0 && run >= 4 {
+            out.push((l, 0));
+            let n = (run - 1).min(6);
+            out.push((16, (n - 3) as u8));
+            i += n + 1;
+        } else {
+            out.push((l, 0));
+            i += 1;
+        }
+    }
+    out
+}
+
+fn write_block(w: &mut BitWriter, tokens: &[Token], last: bool) {
+    let mut lit_freq = [0u32; 286];
+    let mut dist_freq = [0u32; 30];
+    for t in tokens {
+        match *t {
+            Token::Literal(b) => lit_freq[b as usize] += 1,
+            Token::Match { len, dist } => {
+                lit_freq[257 + length_code(len)] += 1;
+                dist_freq[dist_code(dist)] += 1;
+            }
+        }
+    }
+    lit_freq[END_OF_BLOCK] = 1;
+
+    let lit_lengths = code_lengths(&lit_freq, 15);
+    let mut dist_lengths = code_lengths(&dist_freq, 15);
+    // At least one distance code has to be described even if there are no matches
+    if dist_lengths.iter().all(|&l| l == 0) {
+        dist_lengths[0] = 1;
+    }
+    let lit_codes = canonical_codes(&lit_lengths);
+    let dist_codes = canonical_codes(&dist_lengths);
+
+    let hlit = 257.max(lit_lengths.iter().rposition(|&l| l > 0).unwrap() + 1);
+    let hdist = 1.max(dist_lengths.iter().rposition(|&l| l > 0).unwrap() + 1);
+
+    let mut all_lengths = lit_lengths[..hlit].to_vec();
+    all_lengths.extend_from_slice(&dist_lengths[..hdist]);
+    let rle = rle_lengths(&all_lengths);
+
+    let mut cl_freq = [0u32; 19];
+    for &(sym, _) in &rle {
+        cl_freq[sym as usize] += 1;
+    }
+    let cl_lengths = code_lengths(&cl_freq, 7);
+    let cl_codes = canonical_codes(&cl_lengths);
+    let hclen = 4.max(CODE_LENGTH_ORDER.iter().rposition(|&s| cl_lengths[s] > 0).unwrap() + 1);
+
+    // Block header for dynamic Huffman codes
+    w.bits(last as u32, 1);
+    w.bits(2, 2);
+    w.bits((hlit - 257) as u32, 5);
+    w.bits((hdist - 1) as u32, 5);
+    w.bits((hclen - 4) as u32, 4);
+    for &s in &CODE_LENGTH_ORDER[..hclen] {
+        w.bits(cl_lengths[s] as u32, 3);
+    }
+    for &(sym, extra) in &rle {
+        w.code(cl_codes[sym as usize], cl_lengths[sym as usize]);
+        match sym {
+            16 => w.bits(extra as u32, 2),
+            17 => w.bits(extra as u32, 3),
+            18 => w.bits(extra as u32, 7),
+            _ => {}
+        }
+    }
+
+    for t in tokens {
+        match *t {
+            Token::Literal(b) => w.code(lit_codes[b as usize], lit_lengths[b as usize]),
+            Token::Match { len, dist } => {
+                let lc = length_code(len);
+                w.code(lit_codes[257 + lc], lit_lengths[257 + lc]);
+                w.bits((len - LENGTH_BASE[lc]) as u32, LENGTH_EXTRA[lc] as u32);
+
+                let dc = dist_code(dist);
+                w.code(dist_codes[dc], dist_lengths[dc]);
+                w.bits((dist - DIST_BASE[dc]) as u32, DIST_EXTRA[dc] as u32);
+            }
+        }
+    }
+    w.code(lit_codes[END_OF_BLOCK], lit_lengths[END_OF_BLOCK]);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, n: u32) -> u32 {
+            let mut v = 0;
+            for i in 0..n {
+                let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+                v |= (bit as u32) << i;
+                self.pos += 1;
+            }
+            v
+        }
+
+        // Canonical Huffman decoding one bit at a time, as in zlib's puff
+        fn symbol(&mut self, h: &Huffman) -> usize {
+            let (mut code, mut first, mut index) = (0u32, 0u32, 0u32);
+            for len in 1..16 {
+                code |= self.bits(1);
+                if code < first + h.count[len] {
+                    return h.symbols[(index + code - first) as usize];
+                }
+                index += h.count[len];
+                first = (first + h.count[len]) << 1;
+                code <<= 1;
+            }
+            panic!("invalid Huffman code");
+        }
+    }
+
+    // Number of codes of each length and the symbols in code order
+    struct Huffman {
+        count: [u32; 16],
+        symbols: Vec<usize>
+    }
+
+    impl Huffman {
+        fn new(lengths: &[u8]) -> Huffman {
+            let mut count = [0u32; 16];
+            for &l in lengths {
+                count[l as usize] += 1;
+            }
+            count[0] = 0;
+            let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] > 0).collect();
+            symbols.sort_by_key(|&s| lengths[s]);
+            Huffman { count, symbols }
+        }
+    }
+
+    // A minimal decoder for stored, fixed and dynamic blocks
+    pub(crate) fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut r = BitReader { data, pos: 0 };
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let last = r.bits(1) == 1;
+            let (lit, dist) = match r.bits(2) {
+                0 => {
+                    r.pos = r.pos.div_ceil(8) * 8;
+                    let len = r.bits(16) as usize;
+                    assert_eq!(r.bits(16) as usize, !len & 0xffff);
+                    let start = r.pos / 8;
+                    out.extend_from_slice(&data[start..start + len]);
+                    r.pos += 8 * len;
+                    if last {
+                        return out;
+                    }
+                    continue;
+                }
+                1 => {
+                    let mut lit = vec![8u8; 288];
+                    lit[144..256].fill(9);
+                    lit[256..280].fill(7);
+                    (Huffman::new(&lit), Huffman::new(&[5u8; 30]))
+                }
+                2 => {
+                    let (hlit, hdist, hclen) = (r.bits(5) as usize + 257, r.bits(5) as usize + 1, r.bits(4) as usize + 4);
+                    let mut cl = [0u8; 19];
+                    for &s in &CODE_LENGTH_ORDER[..hclen] {
+                        cl[s] = r.bits(3) as u8;
+                    }
+                    let cl = Huffman::new(&cl);
+                    let mut lengths = Vec::new();
+                    while lengths.len() < hlit + hdist {
+                        match r.symbol(&cl) {
+                            16 => {
+                                let prev = *lengths.last().unwrap();
+                                lengths.extend(std::iter::repeat_n(prev, 3 + r.bits(2) as usize));
+                            }
+                            17 => lengths.extend(std::iter::repeat_n(0, 3 + r.bits(3) as usize)),
+                            18 => lengths.extend(std::iter::repeat_n(0, 11 + r.bits(7) as usize)),
+                            l => lengths.push(l as u8)
+                        }
+                    }
+                    assert_eq!(lengths.len(), hlit + hdist, "code lengths overran the header's counts");
+                    (Huffman::new(&lengths[..hlit]), Huffman::new(&lengths[hlit..]))
+                }
+                _ => panic!("reserved block type")
+            };
+
+            loop {
+                let sym = r.symbol(&lit);
+                if sym < 256 {
+                    out.push(sym as u8);
+                } else if sym == END_OF_BLOCK {
+                    break;
+                } else {
+                    let lc = sym - 257;
+                    let len = LENGTH_BASE[lc] as usize + r.bits(LENGTH_EXTRA[lc] as u32) as usize;
+                    let dc = r.symbol(&dist);
+                    let d = DIST_BASE[dc] as usize + r.bits(DIST_EXTRA[dc] as u32) as usize;
+                    assert!(d <= out.len(), "distance reaches before the start");
+                    for _ in 0..len {
+                        out.push(out[out.len() - d]);
+                    }
+                }
+            }
+            if last {
+                return out;
+            }
+        }
+    }
+
+    pub(crate) fn zlib_decompress(data: &[u8]) -> Vec<u8> {
+        assert_eq!(data[0] & 0x0f, 8, "not deflate");
+        assert_eq!((data[0] as u32 * 256 + data[1] as u32) % 31, 0, "bad header check bits");
+        let out = inflate(&data[2..data.len() - 4]);
+        assert_eq!(data[data.len() - 4..], adler32(&out).to_be_bytes(), "Adler-32 mismatch");
+        out
+    }
+
+    // Deterministic bytes that don't compress
+    fn noise(n: usize) -> Vec<u8> {
+        let mut x = 0x2545f4914f6cdd1du64;
+        (0..n).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            (x >> 32) as u8
+        }).collect()
+    }
+
+    fn round_trip(data: &[u8]) {
+        assert_eq!(inflate(&deflate(data)), data);
+        assert_eq!(zlib_decompress(&zlib_compress(data)), data);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32_update(crc32(b"1234"), b"56789"), crc32(b"123456789"));
+    }
+
+    #[test]
+    fn adler32_check_value() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn round_trip_empty_and_short() {
+        round_trip(b"");
+        round_trip(b"a");
+        round_trip(b"abc");
+        round_trip(b"hello hello hello hello");
+    }
+
+    #[test]
+    fn round_trip_long_runs() {
+        round_trip(&[0u8; 100_000]);
+        let pattern: Vec<u8> = (0..70_000).map(|i| (i % 251) as u8).collect();
+        round_trip(&pattern);
+    }
+
+    #[test]
+    fn round_trip_incompressible_over_several_blocks() {
+        // More literals than fit in one block
+        round_trip(&noise(3 * BLOCK_TOKENS / 2));
+    }
+
+    #[test]
+    fn round_trip_far_matches() {
+        // Repeats a chunk from the far end of the window
+        let mut data = noise(WINDOW_SIZE - 100);
+        data.extend_from_within(..1000);
+        round_trip(&data);
+        let compressed = deflate(&data);
+        assert!(compressed.len() < data.len(), "the repeat wasn't matched");
+    }
+}
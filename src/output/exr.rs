@@ -0,0 +1,737 @@
+use super::ImageWriter;
+use super::deflate::zlib_compress;
+use crate::image::Image;
+use crate::vec::FloatT;
+
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use std::io::{self, Write};
+
+const MAGIC: u32 = 20000630;
+// Single part scanline file, version 2
+const VERSION: u32 = 2;
+
+/// How EXR scanline blocks are compressed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EXRCompression {
+    /// Raw pixel data, one scanline per block.
+    None,
+    /// Zlib compression of 16 scanlines at a time, good for noisy renders.
+    ZIP,
+    /// Wavelet and Huffman compression of 32 scanlines at a time, good for smooth images.
+    PIZ
+}
+
+impl EXRCompression {
+    fn id(self) -> u8 {
+        match self {
+            EXRCompression::None => 0,
+            EXRCompression::ZIP => 3,
+            EXRCompression::PIZ => 4
+        }
+    }
+
+    fn lines_per_block(self) -> usize {
+        match self {
+            EXRCompression::None => 1,
+            EXRCompression::ZIP => 16,
+            EXRCompression::PIZ => 32
+        }
+    }
+}
+
+/// Storage type of EXR color channels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EXRPixelType {
+    /// 16-bit half floats.
+    Half,
+    /// 32-bit floats.
+    Float
+}
+
+impl EXRPixelType {
+    fn id(self) -> i32 {
+        match self {
+            EXRPixelType::Half => 1,
+            EXRPixelType::Float => 2
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            EXRPixelType::Half => 2,
+            EXRPixelType::Float => 4
+        }
+    }
+}
+
+/// OpenEXR scanline image holding the raw linear radiance in `R`, `G` and `B`. Any extra
+/// channels on the image are written too, always as 32-bit floats so depth keeps its precision.
+pub struct EXRWriter {
+    compression: EXRCompression,
+    pixel_type: EXRPixelType
+}
+
+impl EXRWriter {
+    /// Creates a writer storing color as `pixel_type` with the given compression.
+    pub fn new(compression: EXRCompression, pixel_type: EXRPixelType) -> EXRWriter {
+        EXRWriter { compression, pixel_type }
+    }
+}
+
+struct ExrChannel<'a> {
+    name: &'a str,
+    pixel_type: EXRPixelType,
+    value: Box<dyn Fn(usize) -> FloatT + 'a>
+}
+
+// Converts to an IEEE 754 half float, rounding to nearest even
+fn f32_to_half(f: f32) -> u16 {
+    let bits = f.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        // Infinity stays infinity, NaN keeps a mantissa bit set
+        return sign | 0x7c00 | if mant != 0 { 0x200 } else { 0 };
+    }
+
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if e <= 0 {
+        // Subnormal or zero
+        if e < -10 {
+            return sign;
+        }
+        let m = mant | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let half = m >> shift;
+        let rem = m & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rem > halfway || (rem == halfway && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+
+    let half = ((e as u32) << 10) | (mant >> 13);
+    let rem = mant & 0x1fff;
+    let round = rem > 0x1000 || (rem == 0x1000 && half & 1 == 1);
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity
+    sign | (half + round as u32) as u16
+}
+
+fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn box2i(width: usize, height: usize) -> Vec<u8> {
+    let mut v = Vec::new();
+    for x in [0, 0, width as i32 - 1, height as i32 - 1] {
+        v.extend_from_slice(&x.to_le_bytes());
+    }
+    v
+}
+
+impl ImageWriter for EXRWriter {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let (width, height) = (image.width(), image.height());
+
+        let mut channels: Vec<ExrChannel> = Vec::new();
+        for (a, name) in ["R", "G", "B"].iter().enumerate() {
+            channels.push(ExrChannel {
+                name,
+                pixel_type: self.pixel_type,
+                value: Box::new(move |i| image.pixels()[i][a])
+            });
+        }
+        for c in image.channels() {
+            channels.push(ExrChannel {
+                name: &c.name,
+                pixel_type: EXRPixelType::Float,
+                value: Box::new(move |i| c.data[i])
+            });
+        }
+        // Channels are stored in alphabetical order
+        channels.sort_by(|a, b| a.name.cmp(b.name));
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&MAGIC.to_le_bytes());
+        header.extend_from_slice(&VERSION.to_le_bytes());
+
+        let mut chlist = Vec::new();
+        for c in &channels {
+            chlist.extend_from_slice(c.name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&c.pixel_type.id().to_le_bytes());
+            // pLinear and reserved bytes, then x and y sampling
+            chlist.extend_from_slice(&[0, 0, 0, 0]);
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+
+        attribute(&mut header, "channels", "chlist", &chlist);
+        attribute(&mut header, "compression", "compression", &[self.compression.id()]);
+        attribute(&mut header, "dataWindow", "box2i", &box2i(width, height));
+        attribute(&mut header, "displayWindow", "box2i", &box2i(width, height));
+        // Increasing y
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+        attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+        header.push(0);
+
+        // Compress every block of scanlines before the offset table can be written
+        let lines = self.compression.lines_per_block();
+        let blocks: Vec<Vec<u8>> = (0..height).step_by(lines).map(|y0| {
+            let y1 = (y0 + lines).min(height);
+
+            let mut raw = Vec::new();
+            for y in y0..y1 {
+                for c in &channels {
+                    for x in 0..width {
+                        let v = (c.value)(y * width + x) as f32;
+                        match c.pixel_type {
+                            EXRPixelType::Half => raw.extend_from_slice(&f32_to_half(v).to_le_bytes()),
+                            EXRPixelType::Float => raw.extend_from_slice(&v.to_le_bytes())
+                        }
+                    }
+                }
+            }
+
+            let packed = match self.compression {
+                EXRCompression::None => return raw,
+                EXRCompression::ZIP => zip_compress(&raw),
+                EXRCompression::PIZ => {
+                    let sizes: Vec<usize> = channels.iter().map(|c| c.pixel_type.size() / 2).collect();
+                    piz_compress(&raw, width, y1 - y0, &sizes)
+                }
+            };
+            // Readers treat blocks that didn't shrink as uncompressed
+            if packed.len() < raw.len() { packed } else { raw }
+        }).collect();
+
+        let mut offset = (header.len() + 8 * blocks.len()) as u64;
+        out.write_all(&header)?;
+        for b in &blocks {
+            out.write_all(&offset.to_le_bytes())?;
+            offset += 8 + b.len() as u64;
+        }
+        for (i, b) in blocks.iter().enumerate() {
+            out.write_all(&((i * lines) as i32).to_le_bytes())?;
+            out.write_all(&(b.len() as i32).to_le_bytes())?;
+            out.write_all(b)?;
+        }
+        Ok(())
+    }
+}
+
+// Splits bytes into even and odd halves and delta encodes them before deflating
+fn zip_compress(raw: &[u8]) -> Vec<u8> {
+    let mut tmp: Vec<u8> = raw.iter().step_by(2).chain(raw.iter().skip(1).step_by(2)).copied().collect();
+
+    let mut p = tmp.first().copied().unwrap_or(0);
+    for v in tmp.iter_mut().skip(1) {
+        let orig = *v;
+        *v = orig.wrapping_sub(p).wrapping_add(128);
+        p = orig;
+    }
+    zlib_compress(&tmp)
+}
+
+const BITMAP_SIZE: usize = 8192;
+
+// PIZ: maps used 16-bit values to a dense range, applies a Haar wavelet per channel
+// and Huffman codes the result
+fn piz_compress(raw: &[u8], width: usize, lines: usize, sizes: &[usize]) -> Vec<u8> {
+    // Regroup the scanline-interleaved data so each channel is contiguous
+    let words: Vec<u16> = raw.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    let mut starts = Vec::with_capacity(sizes.len());
+    let mut total = 0;
+    for &size in sizes {
+        starts.push(total);
+        total += width * lines * size;
+    }
+
+    let mut data = vec![0u16; total];
+    let mut ends = starts.clone();
+    let mut src = 0;
+    for _ in 0..lines {
+        for (c, &size) in sizes.iter().enumerate() {
+            let n = width * size;
+            data[ends[c]..ends[c] + n].copy_from_slice(&words[src..src + n]);
+            src += n;
+            ends[c] += n;
+        }
+    }
+
+    // Bitmap of the values in use, zero is always assumed present
+    let mut bitmap = vec![0u8; BITMAP_SIZE];
+    for &v in &data {
+        bitmap[(v >> 3) as usize] |= 1 << (v & 7);
+    }
+    bitmap[0] &= !1;
+    let min_non_zero = bitmap.iter().position(|&b| b != 0).unwrap_or(BITMAP_SIZE - 1);
+    let max_non_zero = bitmap.iter().rposition(|&b| b != 0).unwrap_or(0);
+
+    let mut lut = vec![0u16; 1 << 16];
+    let mut k = 0u16;
+    for (i, entry) in lut.iter_mut().enumerate() {
+        if i == 0 || bitmap[i >> 3] & (1 << (i & 7)) != 0 {
+            *entry = k;
+            k += 1;
+        }
+    }
+    let max_value = k - 1;
+    for v in data.iter_mut() {
+        *v = lut[*v as usize];
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&(min_non_zero as u16).to_le_bytes());
+    out.extend_from_slice(&(max_non_zero as u16).to_le_bytes());
+    if min_non_zero <= max_non_zero {
+        out.extend_from_slice(&bitmap[min_non_zero..=max_non_zero]);
+    }
+
+    for (c, &size) in sizes.iter().enumerate() {
+        for j in 0..size {
+            wav2_encode(&mut data[starts[c] + j..], width, size, lines, width * size, max_value);
+        }
+    }
+
+    let huf = huf_compress(&data);
+    out.extend_from_slice(&(huf.len() as i32).to_le_bytes());
+    out.extend_from_slice(&huf);
+    out
+}
+
+fn wenc14(a: u16, b: u16) -> (u16, u16) {
+    let (a, b) = (a as i16 as i32, b as i16 as i32);
+    (((a + b) >> 1) as u16, (a - b) as u16)
+}
+
+fn wenc16(a: u16, b: u16) -> (u16, u16) {
+    const A_OFFSET: i32 = 1 << 15;
+    const M_OFFSET: i32 = 1 << 15;
+    const MOD_MASK: i32 = (1 << 16) - 1;
+
+    let ao = (a as i32 + A_OFFSET) & MOD_MASK;
+    let mut m = (ao + b as i32) >> 1;
+    let d = ao - b as i32;
+    if d < 0 {
+        m = (m + M_OFFSET) & MOD_MASK;
+    }
+    (m as u16, (d & MOD_MASK) as u16)
+}
+
+// 2D Haar wavelet over an `nx` by `ny` grid with element stride `ox` and row stride `oy`
+fn wav2_encode(buf: &mut [u16], nx: usize, ox: usize, ny: usize, oy: usize, mx: u16) {
+    let w14 = mx < (1 << 14);
+    let enc = |a: u16, b: u16| if w14 { wenc14(a, b) } else { wenc16(a, b) };
+    let n = nx.min(ny);
+    let mut p = 1;
+    let mut p2 = 2;
+
+    while p2 <= n {
+        let (oy1, oy2, ox1, ox2) = (oy * p, oy * p2, ox * p, ox * p2);
+
+        let mut py = 0;
+        while py + oy2 <= oy * ny {
+            let mut px = py;
+            while px + ox2 <= py + ox * nx {
+                let (p01, p10) = (px + ox1, px + oy1);
+                let p11 = p10 + ox1;
+
+                let (i00, i01) = enc(buf[px], buf[p01]);
+                let (i10, i11) = enc(buf[p10], buf[p11]);
+                (buf[px], buf[p10]) = enc(i00, i10);
+                (buf[p01], buf[p11]) = enc(i01, i11);
+                px += ox2;
+            }
+
+            // Odd column
+            if nx & p != 0 {
+                let p10 = px + oy1;
+                let (i00, h) = enc(buf[px], buf[p10]);
+                buf[p10] = h;
+                buf[px] = i00;
+            }
+            py += oy2;
+        }
+
+        // Odd line
+        if ny & p != 0 {
+            let mut px = py;
+            while px + ox2 <= py + ox * nx {
+                let p01 = px + ox1;
+                let (i00, h) = enc(buf[px], buf[p01]);
+                buf[p01] = h;
+                buf[px] = i00;
+                px += ox2;
+            }
+        }
+
+        p = p2;
+        p2 <<= 1;
+    }
+}
+
+const HUF_ENCSIZE: usize = (1 << 16) + 1;
+const SHORT_ZEROCODE_RUN: u64 = 59;
+const LONG_ZEROCODE_RUN: u64 = 63;
+const SHORTEST_LONG_RUN: usize = 2 + LONG_ZEROCODE_RUN as usize - SHORT_ZEROCODE_RUN as usize;
+const LONGEST_LONG_RUN: usize = 255 + SHORTEST_LONG_RUN;
+// Longest code the OpenEXR decoder accepts
+const HUF_MAX_LENGTH: u8 = 58;
+
+// Most significant bit first bit packing
+struct HufBits {
+    out: Vec<u8>,
+    acc: u128,
+    n: u32
+}
+
+impl HufBits {
+    fn bits(&mut self, n: u32, bits: u64) {
+        self.acc = (self.acc << n) | bits as u128;
+        self.n += n;
+        while self.n >= 8 {
+            self.n -= 8;
+            self.out.push((self.acc >> self.n) as u8);
+        }
+    }
+
+    fn code(&mut self, (code, len): (u64, u8)) {
+        self.bits(len as u32, code);
+    }
+
+    fn finish(mut self) -> (Vec<u8>, usize) {
+        let n_bits = self.out.len() * 8 + self.n as usize;
+        if self.n > 0 {
+            self.out.push((self.acc << (8 - self.n)) as u8);
+        }
+        (self.out, n_bits)
+    }
+}
+
+fn huf_code_lengths(freq: &[u64]) -> Vec<u8> {
+    let mut lengths = vec![0u8; freq.len()];
+    // Every node keeps a list of the symbols below it, each merge adds a bit to all of them
+    let mut members: Vec<Vec<usize>> = Vec::new();
+    let mut heap = BinaryHeap::new();
+    for (s, &f) in freq.iter().enumerate() {
+        if f > 0 {
+            heap.push(Reverse((f, members.len())));
+            members.push(vec![s]);
+        }
+    }
+
+    while heap.len() > 1 {
+        let Reverse((f0, a)) = heap.pop().unwrap();
+        let Reverse((f1, b)) = heap.pop().unwrap();
+        let mut merged = std::mem::take(&mut members[a]);
+        merged.append(&mut members[b]);
+        for &s in &merged {
+            lengths[s] += 1;
+        }
+        assert!(merged.iter().all(|&s| lengths[s] <= HUF_MAX_LENGTH), "Huffman code too long for EXR");
+        heap.push(Reverse((f0 + f1, members.len())));
+        members.push(merged);
+    }
+    lengths
+}
+
+// Canonical codes as assigned by OpenEXR, longest codes get the smallest values
+fn huf_canonical_codes(lengths: &[u8]) -> Vec<(u64, u8)> {
+    let mut n = [0u64; 59];
+    for &l in lengths {
+        n[l as usize] += 1;
+    }
+    let mut c = 0u64;
+    for i in (1..=58).rev() {
+        let nc = (c + n[i]) >> 1;
+        n[i] = c;
+        c = nc;
+    }
+    lengths.iter().map(|&l| {
+        if l == 0 {
+            (0, 0)
+        } else {
+            let code = n[l as usize];
+            n[l as usize] += 1;
+            (code, l)
+        }
+    }).collect()
+}
+
+fn huf_compress(raw: &[u16]) -> Vec<u8> {
+    if raw.is_empty() {
+        return Vec::new();
+    }
+
+    let mut freq = vec![0u64; HUF_ENCSIZE];
+    for &v in raw {
+        freq[v as usize] += 1;
+    }
+    let im = freq.iter().position(|&f| f > 0).unwrap();
+    // One past the largest value is a pseudo-symbol marking runs
+    let rlc = freq.iter().rposition(|&f| f > 0).unwrap() + 1;
+    freq[rlc] = 1;
+
+    let codes = huf_canonical_codes(&huf_code_lengths(&freq));
+
+    // Code length table, with runs of unused symbols collapsed
+    let mut table = HufBits { out: Vec::new(), acc: 0, n: 0 };
+    let mut i = im;
+    while i <= rlc {
+        let l = codes[i].1;
+        if l == 0 {
+            let mut zerun = 1;
+            while i < rlc && zerun < LONGEST_LONG_RUN && codes[i + 1].1 == 0 {
+                i += 1;
+                zerun += 1;
+            }
+            if zerun >= 2 {
+                if zerun >= SHORTEST_LONG_RUN {
+                    table.bits(6, LONG_ZEROCODE_RUN);
+                    table.bits(8, (zerun - SHORTEST_LONG_RUN) as u64);
+                } else {
+                    table.bits(6, SHORT_ZEROCODE_RUN + zerun as u64 - 2);
+                }
+                i += 1;
+                continue;
+            }
+        }
+        table.bits(6, l as u64);
+        i += 1;
+    }
+    let (table, _) = table.finish();
+
+    // Symbols, with repeats sent as the symbol, the run code and an 8-bit count when shorter
+    let mut data = HufBits { out: Vec::new(), acc: 0, n: 0 };
+    let send = |s: u16, run: usize, data: &mut HufBits| {
+        let (code, run_code) = (codes[s as usize], codes[rlc]);
+        if (code.1 as usize) + (run_code.1 as usize) + 8 < (code.1 as usize) * run {
+            data.code(code);
+            data.code(run_code);
+            data.bits(8, run as u64);
+        } else {
+            for _ in 0..=run {
+                data.code(code);
+            }
+        }
+    };
+    let mut s = raw[0];
+    let mut run = 0;
+    for &v in &raw[1..] {
+        if v == s && run < 255 {
+            run += 1;
+        } else {
+            send(s, run, &mut data);
+            run = 0;
+        }
+        s = v;
+    }
+    send(s, run, &mut data);
+    let (data, n_bits) = data.finish();
+
+    let mut out = Vec::with_capacity(20 + table.len() + data.len());
+    for v in [im as u32, rlc as u32, table.len() as u32, n_bits as u32, 0] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.extend_from_slice(&table);
+    out.extend_from_slice(&data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::deflate::tests::zlib_decompress;
+    use crate::vec::Color;
+
+    const WIDTH: usize = 5;
+    const HEIGHT: usize = 20;
+
+    // Values every pixel type stores exactly
+    fn test_image() -> Image {
+        let mut image = Image::new(WIDTH, HEIGHT);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                image.set(x, y, Color::new(x as FloatT * 0.25, y as FloatT * 0.5, 1.0));
+            }
+        }
+        image.add_channel("Z", (0..WIDTH * HEIGHT).map(|i| i as FloatT * 0.125).collect());
+        image
+    }
+
+    struct Reader<'a> {
+        data: &'a [u8],
+        pos: usize
+    }
+
+    impl Reader<'_> {
+        fn bytes(&mut self, n: usize) -> &[u8] {
+            self.pos += n;
+            &self.data[self.pos - n..self.pos]
+        }
+
+        fn i32(&mut self) -> i32 {
+            i32::from_le_bytes(self.bytes(4).try_into().unwrap())
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.data[self.pos..].iter().position(|&b| b == 0).unwrap();
+            let s = String::from_utf8(self.bytes(len).to_vec()).unwrap();
+            self.pos += 1;
+            s
+        }
+    }
+
+    struct Parsed {
+        // Header attributes by name
+        attributes: Vec<(String, Vec<u8>)>,
+        // First line and data of each block, in offset table order
+        blocks: Vec<(usize, Vec<u8>)>
+    }
+
+    fn parse(file: &[u8], lines: usize) -> Parsed {
+        let mut r = Reader { data: file, pos: 0 };
+        assert_eq!(r.i32() as u32, MAGIC);
+        assert_eq!(r.i32() as u32, VERSION);
+
+        let mut attributes = Vec::new();
+        loop {
+            let name = r.string();
+            if name.is_empty() {
+                break;
+            }
+            r.string();
+            let size = r.i32() as usize;
+            attributes.push((name, r.bytes(size).to_vec()));
+        }
+
+        let offsets: Vec<usize> = (0..HEIGHT.div_ceil(lines))
+            .map(|_| u64::from_le_bytes(r.bytes(8).try_into().unwrap()) as usize).collect();
+        let blocks = offsets.iter().map(|&offset| {
+            assert_eq!(offset, r.pos);
+            let y = r.i32() as usize;
+            let size = r.i32() as usize;
+            (y, r.bytes(size).to_vec())
+        }).collect();
+        assert_eq!(r.pos, file.len());
+        Parsed { attributes, blocks }
+    }
+
+    // Channel names and pixel type ids from a chlist attribute
+    fn channel_list(value: &[u8]) -> Vec<(String, i32)> {
+        let mut r = Reader { data: value, pos: 0 };
+        let mut channels = Vec::new();
+        loop {
+            let name = r.string();
+            if name.is_empty() {
+                return channels;
+            }
+            let kind = r.i32();
+            r.bytes(4);
+            assert_eq!((r.i32(), r.i32()), (1, 1));
+            channels.push((name, kind));
+        }
+    }
+
+    // Undoes the delta encoding and byte split of a ZIP block
+    fn zip_decompress(packed: &[u8]) -> Vec<u8> {
+        let mut tmp = zlib_decompress(packed);
+        for i in 1..tmp.len() {
+            tmp[i] = tmp[i].wrapping_add(tmp[i - 1]).wrapping_sub(128);
+        }
+        let half = tmp.len().div_ceil(2);
+        (0..tmp.len()).map(|i| if i % 2 == 0 { tmp[i / 2] } else { tmp[half + i / 2] }).collect()
+    }
+
+    fn half_to_f32(h: u16) -> f32 {
+        let (exp, mant) = ((h >> 10) & 0x1f, (h & 0x3ff) as f32);
+        let magnitude = if exp == 0 {
+            mant * (2.0f32).powi(-24)
+        } else {
+            (1.0 + mant / 1024.0) * (2.0f32).powi(exp as i32 - 15)
+        };
+        if h & 0x8000 != 0 { -magnitude } else { magnitude }
+    }
+
+    #[test]
+    fn half_conversion_rounds_to_nearest() {
+        for f in [0.0, 1.0, -2.5, 0.125, 65504.0, 6.1035156e-5, 5.9604645e-8] {
+            assert_eq!(half_to_f32(f32_to_half(f)), f);
+        }
+        assert_eq!(f32_to_half(1.0 + 1.0 / 4096.0), f32_to_half(1.0));
+        assert_eq!(f32_to_half(1e6), 0x7c00);
+    }
+
+    #[test]
+    fn every_compression_and_pixel_type_decodes() {
+        let image = test_image();
+        for compression in [EXRCompression::None, EXRCompression::ZIP, EXRCompression::PIZ] {
+            for pixel_type in [EXRPixelType::Half, EXRPixelType::Float] {
+                let mut file = Vec::new();
+                EXRWriter::new(compression, pixel_type).write(&image, &mut file).unwrap();
+                let lines = compression.lines_per_block();
+                let Parsed { attributes, blocks } = parse(&file, lines);
+                let attribute = |name: &str| &attributes.iter().find(|(n, _)| n == name).unwrap().1;
+
+                let channels = channel_list(attribute("channels"));
+                let color = pixel_type.id();
+                assert_eq!(channels, [("B".to_string(), color), ("G".to_string(), color), ("R".to_string(), color),
+                                      ("Z".to_string(), EXRPixelType::Float.id())]);
+                assert_eq!(attribute("compression"), &[compression.id()]);
+                assert_eq!(attribute("dataWindow"), &box2i(WIDTH, HEIGHT));
+
+                let line_size = WIDTH * (3 * pixel_type.size() + 4);
+                for (i, (y0, data)) in blocks.iter().enumerate() {
+                    assert_eq!(*y0, i * lines);
+                    let raw_size = line_size * lines.min(HEIGHT - y0);
+                    assert!(data.len() <= raw_size, "{:?} block grew", compression);
+                    let raw = match compression {
+                        _ if data.len() == raw_size => data.clone(),
+                        EXRCompression::ZIP => zip_decompress(data),
+                        // PIZ blocks are only checked for shrinking
+                        _ => continue
+                    };
+                    assert_eq!(raw.len(), raw_size);
+
+                    let mut r = Reader { data: &raw, pos: 0 };
+                    for y in *y0..y0 + lines.min(HEIGHT - y0) {
+                        let mut values = [[0.0f32; WIDTH]; 4];
+                        for (c, row) in values.iter_mut().enumerate() {
+                            for v in row.iter_mut() {
+                                *v = match (c, pixel_type) {
+                                    (0..=2, EXRPixelType::Half) => {
+                                        half_to_f32(u16::from_le_bytes(r.bytes(2).try_into().unwrap()))
+                                    }
+                                    _ => f32::from_le_bytes(r.bytes(4).try_into().unwrap())
+                                };
+                            }
+                        }
+                        for x in 0..WIDTH {
+                            let p = image.get(x, y);
+                            let expected = [p.z(), p.y(), p.x(), image.channels()[0].data[y * WIDTH + x]];
+                            for ((row, want), (name, _)) in values.iter().zip(expected).zip(&channels) {
+                                assert_eq!(row[x] as FloatT, want, "{:?} {:?} channel {} at {},{}",
+                                           compression, pixel_type, name, x, y);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
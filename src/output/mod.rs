@@ -0,0 +1,83 @@
+use super::image::Image;
+use super::tonemap::ToneMap;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+mod ppm;
+mod pfm;
+mod bmp;
+mod png;
+mod exr;
+pub mod deflate;
+
+pub use ppm::{PPMWriter, PlainPPMWriter};
+pub use pfm::PFMWriter;
+pub use bmp::BMPWriter;
+pub use png::PNGWriter;
+pub use exr::{EXRWriter, EXRCompression, EXRPixelType};
+
+/// Settings for formats that support more than one encoding.
+pub struct OutputOptions {
+    /// Tone mapping for formats with a fixed display range (PPM, BMP and PNG).
+    pub tone_map: ToneMap,
+    /// Bits per channel for PNG output, 8 or 16.
+    pub bit_depth: u8,
+    /// Compression used for EXR output.
+    pub exr_compression: EXRCompression,
+    /// Storage type of the color channels in EXR output.
+    pub exr_pixel_type: EXRPixelType
+}
+
+impl Default for OutputOptions {
+    fn default() -> OutputOptions {
+        OutputOptions {
+            tone_map: ToneMap::default(),
+            bit_depth: 8,
+            exr_compression: EXRCompression::ZIP,
+            exr_pixel_type: EXRPixelType::Half
+        }
+    }
+}
+
+/// Encodes an [`Image`] in some file format.
+pub trait ImageWriter {
+    /// Writes the whole encoded image to `out`.
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Picks a writer from the extension of `path`, matched case-insensitively:
+///
+/// | Extension | Format |
+/// |-----------|--------|
+/// | `.ppm`    | binary PPM (P6) |
+/// | `.pnm`    | plain text PPM (P3) |
+/// | `.pfm`    | 32-bit float PFM |
+/// | `.bmp`    | 24-bit BMP |
+/// | `.png`    | 8 or 16-bit PNG |
+/// | `.exr`    | OpenEXR, half or float, with any extra channels |
+pub fn writer_for_path(path: &Path, opts: &OutputOptions) -> Option<Box<dyn ImageWriter>> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "ppm" => Some(Box::new(PPMWriter::new(opts.tone_map))),
+        "pnm" => Some(Box::new(PlainPPMWriter::new(opts.tone_map))),
+        "pfm" => Some(Box::new(PFMWriter)),
+        "bmp" => Some(Box::new(BMPWriter::new(opts.tone_map))),
+        "png" => Some(Box::new(PNGWriter::new(opts.bit_depth, opts.tone_map))),
+        "exr" => Some(Box::new(EXRWriter::new(opts.exr_compression, opts.exr_pixel_type))),
+        _ => None
+    }
+}
+
+/// Writes `image` to `path` in the format chosen by [`writer_for_path`].
+pub fn save(image: &Image, path: &Path, opts: &OutputOptions) -> io::Result<()> {
+    let Some(writer) = writer_for_path(path, opts) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("unsupported image format '{}'", path.display())));
+    };
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writer.write(image, &mut out)?;
+    out.flush()
+}
@@ -0,0 +1,25 @@
+use super::ImageWriter;
+use crate::image::Image;
+
+use std::io::{self, Write};
+
+/// Portable float map with 32-bit linear RGB, keeping the full dynamic range.
+pub struct PFMWriter;
+
+impl ImageWriter for PFMWriter {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        // A negative scale marks the data as little endian
+        write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+
+        // PFM stores rows bottom to top
+        for y in (0..image.height()).rev() {
+            for x in 0..image.width() {
+                let c = image.get(x, y);
+                for i in 0..3 {
+                    out.write_all(&(c[i] as f32).to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
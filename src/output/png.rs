@@ -0,0 +1,207 @@
+use super::ImageWriter;
+use super::deflate::{crc32, crc32_update, zlib_compress};
+use crate::image::Image;
+use crate::tonemap::ToneMap;
+
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// Truecolor RGB without alpha
+const COLOR_TYPE_RGB: u8 = 2;
+
+/// PNG with 8 or 16 bits per channel.
+pub struct PNGWriter {
+    bit_depth: u8,
+    tone_map: ToneMap
+}
+
+impl PNGWriter {
+    /// Creates a writer for 8 or 16 bit channels that converts colors with `tone_map`.
+    pub fn new(bit_depth: u8, tone_map: ToneMap) -> PNGWriter {
+        assert!(bit_depth == 8 || bit_depth == 16, "PNG bit depth must be 8 or 16");
+        PNGWriter { bit_depth, tone_map }
+    }
+
+    fn chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        out.write_all(&(data.len() as u32).to_be_bytes())?;
+        out.write_all(kind)?;
+        out.write_all(data)?;
+        out.write_all(&crc32_update(crc32(kind), data).to_be_bytes())
+    }
+
+    // Raw big endian samples of every row, without filter bytes
+    fn rows(&self, image: &Image) -> Vec<Vec<u8>> {
+        (0..image.height()).map(|y| {
+            let mut row = Vec::with_capacity(image.width() * 3 * (self.bit_depth as usize / 8));
+            for x in 0..image.width() {
+                let c = image.get(x, y);
+                if self.bit_depth == 8 {
+                    row.extend_from_slice(&self.tone_map.quantize(c));
+                } else {
+                    for v in self.tone_map.quantize16(c) {
+                        row.extend_from_slice(&v.to_be_bytes());
+                    }
+                }
+            }
+            row
+        }).collect()
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Applies filter type `kind` to `row` given the unfiltered row above it
+fn filter(kind: u8, row: &[u8], prior: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    out.clear();
+    out.push(kind);
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prior[i];
+        let c = if i >= bpp { prior[i - bpp] } else { 0 };
+        let predicted = match kind {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c)
+        };
+        out.push(row[i].wrapping_sub(predicted));
+    }
+}
+
+impl ImageWriter for PNGWriter {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&SIGNATURE)?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(image.width() as u32).to_be_bytes());
+        header.extend_from_slice(&(image.height() as u32).to_be_bytes());
+        // Bit depth, color type, then default compression, filtering and no interlacing
+        header.extend_from_slice(&[self.bit_depth, COLOR_TYPE_RGB, 0, 0, 0]);
+        Self::chunk(out, b"IHDR", &header)?;
+
+        // Pick the filter per row that gives the smallest sum of absolute differences
+        let bpp = 3 * self.bit_depth as usize / 8;
+        let rows = self.rows(image);
+        let zero_row = vec![0u8; rows.first().map_or(0, |r| r.len())];
+        let mut filtered = Vec::new();
+        let (mut candidate, mut best) = (Vec::new(), Vec::new());
+
+        for (y, row) in rows.iter().enumerate() {
+            let prior = if y == 0 { &zero_row } else { &rows[y - 1] };
+            let mut best_score = u64::MAX;
+
+            for kind in 0..5 {
+                filter(kind, row, prior, bpp, &mut candidate);
+                let score: u64 = candidate[1..].iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+                if score < best_score {
+                    best_score = score;
+                    std::mem::swap(&mut best, &mut candidate);
+                }
+            }
+            filtered.extend_from_slice(&best);
+        }
+
+        Self::chunk(out, b"IDAT", &zlib_compress(&filtered))?;
+        Self::chunk(out, b"IEND", &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::deflate::tests::zlib_decompress;
+    use crate::vec::{Color, FloatT};
+
+    // Splits a PNG into its chunks, checking the CRC of each
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut i = 8;
+        while i < png.len() {
+            let len = u32::from_be_bytes(png[i..i + 4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = png[i + 4..i + 8].try_into().unwrap();
+            let data = png[i + 8..i + 8 + len].to_vec();
+            let crc = u32::from_be_bytes(png[i + 8 + len..i + 12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(&png[i + 4..i + 8 + len]), "bad CRC for {:?}", kind);
+            chunks.push((kind, data));
+            i += 12 + len;
+        }
+        chunks
+    }
+
+    // Undoes the per-row filters
+    fn unfilter(data: &[u8], stride: usize, bpp: usize) -> Vec<Vec<u8>> {
+        let mut rows: Vec<Vec<u8>> = Vec::new();
+        for line in data.chunks(stride + 1) {
+            let prior = rows.last().cloned().unwrap_or(vec![0; stride]);
+            let mut row = vec![0u8; stride];
+            for i in 0..stride {
+                let a = if i >= bpp { row[i - bpp] } else { 0 };
+                let (b, c) = (prior[i], if i >= bpp { prior[i - bpp] } else { 0 });
+                let predicted = match line[0] {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    4 => paeth(a, b, c),
+                    k => panic!("unknown filter {}", k)
+                };
+                row[i] = line[1 + i].wrapping_add(predicted);
+            }
+            rows.push(row);
+        }
+        rows
+    }
+
+    fn test_image() -> Image {
+        let mut image = Image::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                image.set(x, y, Color::new(x as FloatT * 0.2, y as FloatT * 0.6, 1.0));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn chunks_have_valid_crcs_and_pixels() {
+        let (image, tone_map) = (test_image(), ToneMap::default());
+        let mut png = Vec::new();
+        PNGWriter::new(8, tone_map).write(&image, &mut png).unwrap();
+
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(k, _)| k).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, COLOR_TYPE_RGB, 0, 0, 0]);
+
+        let rows = unfilter(&zlib_decompress(&chunks[1].1), 9, 3);
+        for (y, row) in rows.iter().enumerate() {
+            let expected: Vec<u8> = (0..3).flat_map(|x| tone_map.quantize(image.get(x, y))).collect();
+            assert_eq!(*row, expected);
+        }
+    }
+
+    #[test]
+    fn sixteen_bit_samples_are_big_endian() {
+        let (image, tone_map) = (test_image(), ToneMap::default());
+        let mut png = Vec::new();
+        PNGWriter::new(16, tone_map).write(&image, &mut png).unwrap();
+
+        let chunks = chunks(&png);
+        assert_eq!(chunks[0].1[8], 16);
+        let rows = unfilter(&zlib_decompress(&chunks[1].1), 18, 6);
+        let [r, g, b] = tone_map.quantize16(image.get(1, 0));
+        assert_eq!(rows[0][6..12], [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat());
+    }
+}
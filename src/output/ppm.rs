@@ -0,0 +1,52 @@
+use super::ImageWriter;
+use crate::image::Image;
+use crate::tonemap::ToneMap;
+
+use std::io::{self, Write};
+
+/// Binary PPM (P6) with 8 bits per channel.
+pub struct PPMWriter {
+    tone_map: ToneMap
+}
+
+/// Plain text PPM (P3) with one pixel per line.
+pub struct PlainPPMWriter {
+    tone_map: ToneMap
+}
+
+impl PPMWriter {
+    /// Creates a writer that converts colors with `tone_map`.
+    pub fn new(tone_map: ToneMap) -> PPMWriter {
+        PPMWriter { tone_map }
+    }
+}
+
+impl PlainPPMWriter {
+    /// Creates a writer that converts colors with `tone_map`.
+    pub fn new(tone_map: ToneMap) -> PlainPPMWriter {
+        PlainPPMWriter { tone_map }
+    }
+}
+
+impl ImageWriter for PPMWriter {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", image.width(), image.height())?;
+        for c in image.pixels() {
+            out.write_all(&self.tone_map.quantize(*c))?;
+        }
+        Ok(())
+    }
+}
+
+impl ImageWriter for PlainPPMWriter {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", image.width(), image.height())?;
+        writeln!(out, "255")?;
+        for c in image.pixels() {
+            let [r, g, b] = self.tone_map.quantize(*c);
+            writeln!(out, "{} {} {}", r, g, b)?;
+        }
+        Ok(())
+    }
+}
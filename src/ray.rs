@@ -1,12 +1,14 @@
 use super::vec::{Vec3, Point3, FloatT};
 
 #[derive(Clone, Copy)]
+/// A half-line starting at an origin.
 pub struct Ray {
     orig: Point3,
     dir: Vec3
 }
 
 impl Ray {
+    /// Creates a ray; the direction doesn't need to be normalized.
     pub fn new(origin: Point3, direction: Vec3) -> Ray {
         Ray {
             orig: origin,
@@ -14,14 +16,17 @@ impl Ray {
         }
     }
 
+    /// Starting point of the ray.
     pub fn origin(&self) -> Point3 {
         self.orig
     }
 
+    /// Direction of the ray.
     pub fn direction(&self) -> Vec3 {
         self.dir
     }
 
+    /// Point at parameter `t` along the ray.
     pub fn at(self, t: FloatT) -> Point3 {
         self.orig + t * self.dir
     }
@@ -0,0 +1,108 @@
+use super::vec::{Point3, Vec3, FloatT};
+use super::ray::Ray;
+use super::hit::{Hit, HitRecord};
+use super::material::BSDF;
+use super::aabb::AABB;
+use super::light::Shape;
+
+use std::sync::Arc;
+
+// Thickness added to the bounding box so rectangles lying in an axis plane aren't flat
+const BOX_PADDING: FloatT = 1e-4;
+
+/// A parallelogram spanned by two edges from a corner, a rectangle when they're perpendicular.
+/// Its front side is the one `u` × `v` points to.
+pub struct Rect {
+    corner: Point3,
+    u: Vec3,
+    v: Vec3,
+    mat: Arc<dyn BSDF>,
+    normal: Vec3,
+    // Scaled normal that turns a point's offset from the corner into its (u, v) coordinates
+    w: Vec3,
+    area: FloatT
+}
+
+impl Rect {
+    /// Creates a rectangle at `corner` with edges `u` and `v` and material `m`.
+    pub fn new(corner: Point3, u: Vec3, v: Vec3, m: Arc<dyn BSDF>) -> Rect {
+        let n = u.cross(v);
+        Rect {
+            corner,
+            u,
+            v,
+            mat: m,
+            normal: n.normalized(),
+            w: n / n.dot(n),
+            area: n.length()
+        }
+    }
+
+    // Ray parameter and point where `r` crosses the rectangle within `(t_min, t_max)`
+    fn intersect(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> Option<(FloatT, Point3)> {
+        let denom = self.normal.dot(r.direction());
+        // Rays parallel to the plane never cross it
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.corner - r.origin()).dot(self.normal) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let p = r.at(t);
+        let offset = p - self.corner;
+        let a = self.w.dot(offset.cross(self.v));
+        let b = self.w.dot(self.u.cross(offset));
+        ((0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)).then_some((t, p))
+    }
+}
+
+impl Hit for Rect {
+    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> (bool, Option<HitRecord>) {
+        let Some((t, p)) = self.intersect(r, t_min, t_max) else {
+            return (false, None);
+        };
+
+        let mut rec = HitRecord {
+            t,
+            p,
+            mat: self.mat.clone(),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            front_face: false
+        };
+        rec.set_face_normal(r, self.normal);
+
+        (true, Some(rec))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let corners = [self.corner + self.u, self.corner + self.v, self.corner + self.u + self.v];
+        let bbox = corners.iter().fold(AABB::new(self.corner, self.corner), |b, &p| AABB::surrounding_point(b, p));
+        let pad = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        Some(AABB::new(bbox.min() - pad, bbox.max() + pad))
+    }
+
+    fn hit_any(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
+}
+
+impl Shape for Rect {
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<(Point3, Vec3, FloatT)> {
+        let q = self.corner + u.0 * self.u + u.1 * self.v;
+        let pdf = self.pdf(p, q);
+        (pdf > 0.0).then_some((q, self.normal, pdf))
+    }
+
+    fn pdf(&self, p: Point3, q: Point3) -> FloatT {
+        // Uniform over the area, converted to a density over directions from `p`
+        let to_q = q - p;
+        let dist2 = to_q.dot(to_q);
+        let cos = (to_q.dot(self.normal) / dist2.sqrt()).abs();
+        if cos <= 0.0 {
+            return 0.0;
+        }
+        dist2 / (cos * self.area)
+    }
+}
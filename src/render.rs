@@ -0,0 +1,575 @@
+use super::vec::{Vec3, Color, FloatT};
+use super::ray::Ray;
+use super::hit::{Hit, HitRecord};
+use super::camera::Camera;
+use super::image::Image;
+use super::tile::{self, Tile, TileOrder};
+use super::sampler::{Sampler, SamplerType};
+use super::filter::Filter;
+use super::material::Lobe;
+use super::light::{Lights, LightHit, MISHeuristic};
+
+use std::io::{stderr, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Highest chance of a path surviving Russian roulette, so even bright paths can't bounce forever
+const MAX_SURVIVAL: FloatT = 0.95;
+
+/// Extra per-pixel data that can be rendered alongside the color.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuxChannel {
+    /// Distance from the camera to the first surface, infinite where nothing was hit. Stored as `Z`.
+    Depth,
+    /// World-space normal of the first surface facing the camera, zero where nothing was hit.
+    /// Stored as `N.X`, `N.Y` and `N.Z`.
+    Normal,
+    /// Number of samples taken, which varies with adaptive sampling. Stored as `SampleCount`.
+    SampleCount
+}
+
+/// Stops sampling a pixel once its estimate is precise enough.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveSampling {
+    /// A pixel is done when the standard error of its mean luminance falls below this fraction of the mean.
+    pub threshold: FloatT,
+    /// Samples taken before checking the error, too few give unreliable variance estimates.
+    pub min_samples: u64
+}
+
+// Pixels darker than this are compared against it instead, so black pixels can converge
+const MIN_LUMINANCE: FloatT = 0.01;
+
+/// Render settings. Construct with [`Renderer::new`] and adjust the public fields as needed.
+pub struct Renderer {
+    /// Image width in pixels.
+    pub width: u64,
+    /// Image height in pixels.
+    pub height: u64,
+    /// Number of camera rays averaged into each pixel, the maximum with adaptive sampling.
+    pub samples_per_pixel: u64,
+    /// Adaptive sampling settings, None takes `samples_per_pixel` samples everywhere.
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Maximum number of bounces in a path. The ray leaving the last bounce is still traced to
+    /// pick up the light it reaches, so a path has at most `max_depth + 1` rays counting the camera ray.
+    pub max_depth: u64,
+    /// Maximum number of diffuse bounces in a path.
+    pub diffuse_depth: u64,
+    /// Maximum number of specular reflections in a path.
+    pub specular_depth: u64,
+    /// Maximum number of refractions in a path.
+    pub transmission_depth: u64,
+    /// Bounces before Russian roulette may end paths, which it does with a chance that grows as
+    /// the path's throughput falls. Surviving paths are brightened to stay unbiased.
+    pub roulette_depth: u64,
+    /// Seed for the sampler, equal seeds give identical images.
+    pub seed: u64,
+    /// Print tile progress to stderr.
+    pub progress: bool,
+    /// Extra channels to attach to the rendered image.
+    pub aux_channels: Vec<AuxChannel>,
+    /// Width and height of the square tiles the image is split into.
+    pub tile_size: usize,
+    /// Order in which tiles are rendered.
+    pub tile_order: TileOrder,
+    /// Sequence the random numbers of every sample are drawn from.
+    pub sampler: SamplerType,
+    /// Filter used to weight samples into nearby pixels.
+    pub filter: Filter,
+    /// Weighting of light and BSDF samples for lights with a radius.
+    pub mis: MISHeuristic
+}
+
+// The number of samples and, if requested, the depth and normal of one pixel
+struct PixelResult {
+    samples: u64,
+    aux: Option<(FloatT, Vec3)>
+}
+
+// Filtered sums of the samples landing near a tile, including a margin as wide as the filter
+struct TileBuffer {
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize,
+    color: Vec<Color>,
+    weight: Vec<FloatT>
+}
+
+impl TileBuffer {
+    fn new(tile: &Tile, filter: &Filter) -> TileBuffer {
+        let margin = (filter.radius() + 0.5).ceil() as usize;
+        let (width, height) = (tile.width + 2 * margin, tile.height + 2 * margin);
+        TileBuffer {
+            x: tile.x as isize - margin as isize,
+            y: tile.y as isize - margin as isize,
+            width,
+            height,
+            color: vec![Color::default(); width * height],
+            weight: vec![0.0; width * height]
+        }
+    }
+
+    // Adds a sample at image position (px, py) to every pixel whose center is within the filter radius
+    fn splat(&mut self, filter: &Filter, px: FloatT, py: FloatT, color: Color) {
+        let r = filter.radius();
+        // Half open so a box filter of radius 0.5 never hits two pixels
+        let (x0, x1) = ((px - r - 0.5).floor() as isize + 1, (px + r - 0.5).floor() as isize);
+        let (y0, y1) = ((py - r - 0.5).floor() as isize + 1, (py + r - 0.5).floor() as isize);
+        for y in y0.max(self.y)..=y1.min(self.y + self.height as isize - 1) {
+            for x in x0.max(self.x)..=x1.min(self.x + self.width as isize - 1) {
+                let w = filter.eval(px - (x as FloatT + 0.5), py - (y as FloatT + 0.5));
+                let k = (y - self.y) as usize * self.width + (x - self.x) as usize;
+                self.color[k] += w * color;
+                self.weight[k] += w;
+            }
+        }
+    }
+}
+
+impl Renderer {
+    /// Creates a renderer for a `width` x `height` image with 100 Sobol samples per pixel, a depth
+    /// of 16 without per-lobe limits, Russian roulette after 3 bounces, 32 pixel tiles
+    /// rendered in a spiral and the power heuristic.
+    pub fn new(width: u64, height: u64) -> Renderer {
+        Renderer {
+            width,
+            height,
+            samples_per_pixel: 100,
+            adaptive: None,
+            max_depth: 16,
+            diffuse_depth: u64::MAX,
+            specular_depth: u64::MAX,
+            transmission_depth: u64::MAX,
+            roulette_depth: 3,
+            seed: 0,
+            progress: false,
+            aux_channels: Vec::new(),
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            sampler: SamplerType::Sobol,
+            filter: Filter::default(),
+            mis: MISHeuristic::Power
+        }
+    }
+
+    // Radiance arriving along `r`, following the path one bounce at a time
+    fn trace(&self, r: &Ray, world: &dyn Hit, lights: &Lights, sampler: &mut dyn Sampler) -> Color {
+        let mut r = *r;
+        let mut radiance = Color::default();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let (mut diffuse, mut specular, mut transmission) = (0, 0, 0);
+        // Density the last bounce was sampled with, for weighting lights it hits
+        let mut bsdf_pdf = None;
+
+        for bounce in 0..self.max_depth {
+            let (_, hit) = world.hit(&r, 0.001, FloatT::INFINITY);
+            radiance += throughput * self.emission(&r, hit.as_ref(), lights, bsdf_pdf);
+            // Escaped rays already picked up the environment, if the lights include one
+            let Some(rec) = hit else {
+                return radiance;
+            };
+
+            // Lights are also sampled directly at every hit, unless no light could be reflected
+            if !rec.mat.is_delta() {
+                radiance += throughput * lights.get_color(&r, &rec, world, sampler, self.mis);
+            }
+
+            let wo = (-1.0) * r.direction().normalized();
+            let Some(bs) = rec.mat.sample(wo, &rec, sampler) else {
+                return radiance;
+            };
+
+            let (count, limit) = match bs.lobe {
+                Lobe::Diffuse => (&mut diffuse, self.diffuse_depth),
+                Lobe::Specular => (&mut specular, self.specular_depth),
+                Lobe::Transmission => (&mut transmission, self.transmission_depth)
+            };
+            *count += 1;
+            throughput = throughput * bs.weight;
+            bsdf_pdf = (!bs.delta).then_some(bs.pdf);
+            r = Ray::new(rec.p, bs.wi);
+
+            // A path cut off by a depth limit still gathers the lights its last bounce was weighted against
+            if *count > limit || bounce + 1 == self.max_depth {
+                let (_, hit) = world.hit(&r, 0.001, FloatT::INFINITY);
+                return radiance + throughput * self.emission(&r, hit.as_ref(), lights, bsdf_pdf);
+            }
+
+            if bounce + 1 >= self.roulette_depth {
+                let survive = throughput.x().max(throughput.y()).max(throughput.z()).min(MAX_SURVIVAL);
+                if sampler.get_1d() >= survive {
+                    return radiance;
+                }
+                throughput /= survive;
+            }
+        }
+        radiance
+    }
+
+    // Light emitted back along `r` by lights in front of its first hit and by the surface hit,
+    // weighted against light sampling when `r` was sampled from a BSDF with density `bsdf_pdf`
+    fn emission(&self, r: &Ray, hit: Option<&HitRecord>, lights: &Lights, bsdf_pdf: Option<FloatT>) -> Color {
+        let t_max = hit.map_or(FloatT::INFINITY, |rec| rec.t);
+        let mut le = lights.emitted(r, t_max, bsdf_pdf, self.mis);
+        if let Some(rec) = hit {
+            let e = rec.mat.emitted(rec);
+            if !e.near_zero() {
+                let weight = bsdf_pdf.map_or(1.0, |pdf| self.mis.bsdf_weight(pdf, lights.pdf(r, rec.t)));
+                le += e * weight;
+            }
+        }
+        le
+    }
+
+    // Depth and normals share a ray through the pixel center
+    fn wants_aux(&self) -> bool {
+        self.aux_channels.contains(&AuxChannel::Depth) || self.aux_channels.contains(&AuxChannel::Normal)
+    }
+
+    // Point `offset` into pixel (i, j) as fractions of the image's width and height, with rows
+    // counted from the bottom. Pixels are equally wide, so the image spans [0, 1) exactly.
+    fn image_point(&self, i: u64, j: u64, offset: (FloatT, FloatT)) -> (FloatT, FloatT) {
+        ((i as FloatT + offset.0) / self.width as FloatT, (j as FloatT + offset.1) / self.height as FloatT)
+    }
+
+    // Pixel (x, y) counting rows from the top
+    fn render_pixel(&self, world: &dyn Hit, lights: &Lights, cam: &dyn Camera, x: usize, y: usize,
+                    buffer: &mut TileBuffer) -> PixelResult {
+        // The camera's v axis points up
+        let (i, j) = (x as u64, self.height - 1 - y as u64);
+        // Running mean and sum of squared deviations of the luminance, after Welford
+        let (mut mean, mut m2) = (0.0, 0.0);
+        let mut samples = 0;
+
+        let mut sampler = self.sampler.create(self.seed, self.samples_per_pixel);
+        for s in 0..self.samples_per_pixel {
+            sampler.start_pixel_sample(i, j, s);
+            let (random_u, random_v) = sampler.get_2d();
+
+            let (u, v) = self.image_point(i, j, (random_u, random_v));
+
+            // Parts of the image the camera doesn't see stay black
+            let color = match cam.get_ray(u, v, sampler.get_2d()) {
+                Some(r) => self.trace(&r, world, lights, sampler.as_mut()),
+                None => Color::default()
+            };
+            buffer.splat(&self.filter, x as FloatT + random_u, y as FloatT + 1.0 - random_v, color);
+            samples += 1;
+
+            if let Some(adaptive) = self.adaptive {
+                let l = color.luminance();
+                let delta = l - mean;
+                mean += delta / samples as FloatT;
+                m2 += delta * (l - mean);
+
+                if samples >= adaptive.min_samples.max(2) {
+                    let variance = m2 / (samples - 1) as FloatT;
+                    let std_error = (variance / samples as FloatT).sqrt();
+                    if std_error <= adaptive.threshold * mean.max(MIN_LUMINANCE) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Auxiliary data comes from a single ray through the pixel center
+        let aux = if self.wants_aux() {
+            let (u, v) = self.image_point(i, j, (0.5, 0.5));
+            cam.center_ray(u, v).and_then(|r| {
+                world.hit(&r, 0.001, FloatT::INFINITY).1.map(|rec| (rec.t * r.direction().length(), rec.normal))
+            })
+        } else {
+            None
+        };
+        PixelResult {
+            samples,
+            aux
+        }
+    }
+
+    /// Traces `world` lit by `lights` as seen from `cam`, returning the filtered radiance of every
+    /// pixel. Rays that escape the scene are black unless the lights include an
+    /// [`EnvironmentLight`](crate::light::EnvironmentLight).
+    ///
+    /// Tiles are handed out in [`Renderer::tile_order`] to one worker per thread of the rayon pool.
+    pub fn render(&self, world: &dyn Hit, lights: &Lights, cam: &dyn Camera) -> Image {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let want_aux = self.wants_aux();
+        let tiles = tile::tiles(width, height, self.tile_size, self.tile_order);
+
+        // Workers claim the next tile in order, so the scheduling order is kept
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let finished: Mutex<Vec<(usize, TileBuffer, Vec<PixelResult>)>> = Mutex::new(Vec::with_capacity(tiles.len()));
+
+        rayon::scope(|s| {
+            for _ in 0..rayon::current_num_threads() {
+                s.spawn(|_| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(tile) = tiles.get(index) else {
+                        break;
+                    };
+
+                    let mut buffer = TileBuffer::new(tile, &self.filter);
+                    let mut pixels = Vec::with_capacity(tile.width * tile.height);
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            pixels.push(self.render_pixel(world, lights, cam, x, y, &mut buffer));
+                        }
+                    }
+                    finished.lock().unwrap().push((index, buffer, pixels));
+
+                    let count = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if self.progress {
+                        eprint!("\r {:4}/{} tiles", count, tiles.len());
+                        stderr().flush().unwrap();
+                    }
+                });
+            }
+        });
+        if self.progress {
+            eprintln!("\nDone");
+        }
+
+        // Assemble the framebuffer, in tile order so the sums don't depend on which thread finished first
+        let mut finished = finished.into_inner().unwrap();
+        finished.sort_by_key(|(index, _, _)| *index);
+
+        let mut color_sum = vec![Color::default(); width * height];
+        let mut weight_sum = vec![0.0; width * height];
+        let mut depth = vec![FloatT::INFINITY; if want_aux { width * height } else { 0 }];
+        let mut normals = vec![Vec3::default(); depth.len()];
+        let mut sample_counts = vec![0.0; width * height];
+        for (index, buffer, pixels) in finished {
+            for (k, (&c, &w)) in buffer.color.iter().zip(&buffer.weight).enumerate() {
+                let (x, y) = (buffer.x + (k % buffer.width) as isize, buffer.y + (k / buffer.width) as isize);
+                if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    color_sum[y as usize * width + x as usize] += c;
+                    weight_sum[y as usize * width + x as usize] += w;
+                }
+            }
+
+            let tile = tiles[index];
+            for (k, pixel) in pixels.into_iter().enumerate() {
+                let (x, y) = (tile.x + k % tile.width, tile.y + k / tile.width);
+                sample_counts[y * width + x] = pixel.samples as FloatT;
+                if let Some((d, n)) = pixel.aux {
+                    depth[y * width + x] = d;
+                    normals[y * width + x] = n;
+                }
+            }
+        }
+
+        // Negative filter lobes can cancel out almost completely, leave those pixels black
+        let pixels = color_sum.into_iter().zip(weight_sum).map(|(c, w)| {
+            if w.abs() > 1e-8 { c / w } else { Color::default() }
+        }).collect();
+        let mut image = Image::from_pixels(width, height, pixels);
+
+        if self.aux_channels.contains(&AuxChannel::Depth) {
+            image.add_channel("Z", depth);
+        }
+        if self.aux_channels.contains(&AuxChannel::Normal) {
+            for (a, name) in ["N.X", "N.Y", "N.Z"].iter().enumerate() {
+                image.add_channel(name, normals.iter().map(|n| n[a]).collect());
+            }
+        }
+        if self.aux_channels.contains(&AuxChannel::SampleCount) {
+            image.add_channel("SampleCount", sample_counts);
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::Point3;
+    use crate::camera::{PerspectiveCamera, EquirectangularCamera, CubemapCamera};
+    use crate::hit::World;
+    use crate::sphere::Sphere;
+    use crate::material::{Lambertian, Metal, Dielectric};
+    use crate::filter::FilterKind;
+    use crate::light::{EnvironmentLight, PointLight};
+    use crate::environment::Gradient;
+    use crate::scene::Scene;
+    use crate::bvh::BVHTree;
+
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    fn camera(vfov: FloatT) -> PerspectiveCamera {
+        PerspectiveCamera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0),
+                               vfov, 1.0, 0.0, 1.0)
+    }
+
+    // Samples taken by the only pixel of a 1x1 render
+    fn samples_taken(world: &World, cam: &dyn Camera) -> FloatT {
+        let mut renderer = Renderer::new(1, 1);
+        renderer.samples_per_pixel = 256;
+        renderer.adaptive = Some(AdaptiveSampling { threshold: 0.01, min_samples: 16 });
+        renderer.aux_channels = vec![AuxChannel::SampleCount];
+        let lights: Lights = vec![Box::new(EnvironmentLight::new(Arc::new(Gradient::default())))];
+        let image = renderer.render(world, &lights, cam);
+        image.channel("SampleCount").unwrap().data[0]
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_on_flat_pixels() {
+        // A narrow view of the sky barely changes across the pixel
+        assert_eq!(samples_taken(&World::new(), &camera(0.1)), 16.0);
+    }
+
+    #[test]
+    fn adaptive_sampling_runs_to_the_maximum_on_noisy_pixels() {
+        // Half the pixel sees a black sphere and half the sky, so samples are either 0 or bright
+        let mut world = World::new();
+        let black = Arc::new(Lambertian::new(Color::default()));
+        world.push(Box::new(Sphere::new(Point3::new(-1000.0, 0.0, -1.0), 1000.0, black)));
+        assert_eq!(samples_taken(&world, &camera(90.0)), 256.0);
+    }
+
+    #[test]
+    fn splatting_a_constant_gives_it_back() {
+        let color = Color::new(0.25, 0.5, 2.0);
+        let tile = Tile { x: 8, y: 4, width: 6, height: 5 };
+        for kind in [FilterKind::Box, FilterKind::Triangle, FilterKind::Gaussian { sigma: 0.5 },
+                     FilterKind::Mitchell { b: 1.0 / 3.0, c: 1.0 / 3.0 }, FilterKind::Lanczos] {
+            let filter = Filter::new(kind);
+            let mut buffer = TileBuffer::new(&tile, &filter);
+            // Samples on a fine grid over the tile and its margin
+            let steps = 8;
+            for k in 0..buffer.width * buffer.height * steps * steps {
+                let (sx, sy) = (k % (buffer.width * steps), k / (buffer.width * steps));
+                let px = buffer.x as FloatT + (sx as FloatT + 0.5) / steps as FloatT;
+                let py = buffer.y as FloatT + (sy as FloatT + 0.5) / steps as FloatT;
+                buffer.splat(&filter, px, py, color);
+            }
+
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    let k = (y as isize - buffer.y) as usize * buffer.width + (x as isize - buffer.x) as usize;
+                    let c = buffer.color[k] / buffer.weight[k];
+                    assert!((c - color).length() < 1e-9, "{:?} gave {} at ({}, {})", kind, c, x, y);
+                    // A filter that integrates to 1 gets a total weight of one per unit of sample density
+                    let weight = buffer.weight[k] / (steps * steps) as FloatT;
+                    assert!((weight - 1.0).abs() < 1e-2, "{:?} weight {}", kind, weight);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_image() {
+        let world: World = vec![
+            Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0))))),
+            Box::new(Sphere::new(Point3::new(-0.6, 0.0, -1.2), 0.5, Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.3)))),
+            Box::new(Sphere::new(Point3::new(0.6, 0.0, -1.0), 0.5, Arc::new(Dielectric::new(1.5))))
+        ];
+        let lights: Lights = vec![Box::new(PointLight::new(Point3::new(0.0, 3.0, 0.0), Color::new(1.0, 1.0, 1.0), 20.0, 0.5)),
+                                  Box::new(EnvironmentLight::new(Arc::new(Gradient::default())))];
+
+        let mut renderer = Renderer::new(24, 16);
+        renderer.samples_per_pixel = 8;
+        renderer.tile_size = 5;
+        // Wide filters splat across tile edges, where merge order could show up
+        renderer.filter = Filter::new(FilterKind::Lanczos);
+        let cam = camera(60.0);
+        let render = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let image = pool.install(|| renderer.render(&world, &lights, &cam));
+            image.pixels().iter().map(|c| [c.x().to_bits(), c.y().to_bits(), c.z().to_bits()]).collect::<Vec<_>>()
+        };
+        assert_eq!(render(1), render(4));
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean() {
+        // Bright surfaces under a uniform sky bounce light between them many times
+        let white = Arc::new(Lambertian::new(Color::new(0.9, 0.9, 0.9)));
+        let world: World = vec![
+            Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, white.clone())),
+            Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, white))
+        ];
+        let sky = Gradient::new(Color::new(1.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0), 1.0);
+        let lights: Lights = vec![Box::new(EnvironmentLight::new(Arc::new(sky)))];
+
+        let mut renderer = Renderer::new(16, 16);
+        renderer.samples_per_pixel = 64;
+        renderer.max_depth = 64;
+        let cam = camera(90.0);
+        let mut mean = |roulette_depth| {
+            renderer.roulette_depth = roulette_depth;
+            let image = renderer.render(&world, &lights, &cam);
+            image.pixels().iter().map(|c| c.x()).sum::<FloatT>() / image.pixels().len() as FloatT
+        };
+        let (with, without) = (mean(3), mean(64));
+        assert!((with - without).abs() < 0.01 * without, "{} with roulette, {} without", with, without);
+    }
+
+    #[test]
+    fn sampling_strategies_agree_on_the_mean() {
+        // A small scenes/mis.scene: a glossy floor under lights of equal power and growing size,
+        // without the tiniest light that BSDF sampling alone would take far too long to find
+        let src = "image width=32 height=16\ncamera lookfrom=0,2,9 lookat=0,0.6,0 vup=0,1,0 fov=30\n\
+                   material dome lambertian albedo=0,0,0\nmaterial floor metal albedo=0.8,0.8,0.8 fuzz=0.15\n\
+                   sphere center=0,0,0 radius=-50 material=dome\nsphere center=0,-1000,0 radius=1000 material=floor\n\
+                   light position=-2,1.5,-2 color=1,0.3,0.3 luminosity=20 radius=0.2\n\
+                   light position=0,1.5,-2 color=0.3,1,0.3 luminosity=20 radius=0.4\n\
+                   light position=2,1.5,-2 color=0.3,0.3,1 luminosity=20 radius=0.8\n";
+        let scene = Scene::parse(src).unwrap();
+        let world = BVHTree::new(scene.world);
+        let cam = scene.camera.build(2.0);
+        let mut renderer = Renderer::new(32, 16);
+        renderer.samples_per_pixel = 128;
+        // Only direct light, where the strategies differ
+        renderer.max_depth = 1;
+        let mut mean = |mis| {
+            renderer.mis = mis;
+            let image = renderer.render(&world, &scene.lights, cam.as_ref());
+            image.pixels().iter().fold(Color::default(), |sum, &c| sum + c) / image.pixels().len() as FloatT
+        };
+        let reference = mean(MISHeuristic::Power);
+        for mis in [MISHeuristic::Balance, MISHeuristic::LightOnly, MISHeuristic::BSDFOnly] {
+            let m = mean(mis);
+            assert!((m - reference).length() < 0.05 * reference.length(), "{:?} mean {} but MIS gives {}", mis, m, reference);
+        }
+    }
+
+    #[test]
+    fn equirect_columns_wrap_evenly() {
+        let renderer = Renderer::new(36, 18);
+        let cam = EquirectangularCamera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0),
+                                             Vec3::new(0.0, 1.0, 0.0));
+        // Angle around the vertical axis from straight ahead
+        let azimuth = |i| {
+            let (u, v) = renderer.image_point(i, 9, (0.5, 0.5));
+            let d = cam.center_ray(u, v).unwrap().direction();
+            d.x().atan2(-d.z())
+        };
+        let step = 2.0 * PI / renderer.width as FloatT;
+        for i in 0..renderer.width {
+            // The last column meets the first across the seam behind the camera
+            let angle = (azimuth((i + 1) % renderer.width) - azimuth(i)).rem_euclid(2.0 * PI);
+            assert!((angle - step).abs() < 1e-9, "columns {} and {} are {} apart", i, i + 1, angle);
+        }
+    }
+
+    #[test]
+    fn cubemap_faces_are_square() {
+        let renderer = Renderer::new(24, 4);
+        let cam = CubemapCamera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        for i in 0..renderer.width {
+            for j in 0..renderer.height {
+                let (u, v) = renderer.image_point(i, j, (0.5, 0.5));
+                let d = cam.center_ray(u, v).unwrap().direction();
+                // Right, left, up, down, forward and back
+                let facing = [d.x(), -d.x(), d.y(), -d.y(), -d.z(), d.z()];
+                let face = (0..6).max_by(|&a, &b| facing[a].total_cmp(&facing[b])).unwrap();
+                assert_eq!(face as u64, i / renderer.height, "pixel {},{}", i, j);
+            }
+        }
+    }
+}
+
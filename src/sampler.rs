@@ -0,0 +1,667 @@
+use super::vec::{Vec3, FloatT};
+
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+/// Source of the random numbers for every sample of a pixel.
+///
+/// Each call to [`Sampler::get_1d`] or [`Sampler::get_2d`] uses up the next dimension of the
+/// current sample, so callers must request dimensions in the same order for every sample.
+/// The values only depend on the seed, the pixel and the sample index, never on the thread.
+pub trait Sampler {
+    /// Starts sample `index` of pixel (`x`, `y`) from its first dimension.
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: u64);
+
+    /// Next dimension of the current sample, in `[0, 1)`.
+    fn get_1d(&mut self) -> FloatT;
+
+    /// Next two dimensions of the current sample, in `[0, 1)²`.
+    fn get_2d(&mut self) -> (FloatT, FloatT);
+}
+
+/// The available [`Sampler`] implementations.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SamplerType {
+    /// Uncorrelated uniform random numbers.
+    Independent,
+    /// One jittered sample per stratum, in a random order per pixel and dimension.
+    Stratified,
+    /// The Halton sequence, shifted randomly per pixel.
+    Halton,
+    /// Owen-scrambled Sobol points, shuffled independently per dimension.
+    Sobol,
+    /// One Sobol sequence shared by all pixels and offset by a blue noise mask, which spreads
+    /// the remaining error as high frequency noise that looks smoother at low sample counts.
+    BlueNoise
+}
+
+impl SamplerType {
+    /// Creates a sampler for images taking `samples_per_pixel` samples, seeded with `seed`.
+    pub fn create(self, seed: u64, samples_per_pixel: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerType::BlueNoise => Box::new(BlueNoiseSampler::new(seed))
+        }
+    }
+}
+
+// SplitMix64 finalizer, spreads nearby inputs over the whole 64-bit range
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| mix(h ^ v))
+}
+
+fn to_unit(h: u64) -> FloatT {
+    (h >> 11) as FloatT / (1u64 << 53) as FloatT
+}
+
+fn u32_to_unit(v: u32) -> FloatT {
+    v as FloatT / (1u64 << 32) as FloatT
+}
+
+// Element `i` of a random permutation of `0..l` picked by `p`, Kensler's "Correlated Multi-Jittered Sampling"
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            return (i + p) % l;
+        }
+    }
+}
+
+/// Uncorrelated uniform random numbers from a stream per sample.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: StdRng
+}
+
+impl IndependentSampler {
+    /// Creates a sampler seeded with `seed`.
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.rng = StdRng::seed_from_u64(hash(&[self.seed, x, y, index]));
+    }
+
+    fn get_1d(&mut self) -> FloatT {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (FloatT, FloatT) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+/// Jittered stratification of every dimension. 2D dimensions use a grid close to square, and
+/// samples past `samples_per_pixel` start a new round of strata.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    pixel: (u64, u64),
+    index: u64,
+    dimension: u64
+}
+
+impl StratifiedSampler {
+    /// Creates a sampler with `samples_per_pixel` strata per dimension.
+    pub fn new(seed: u64, samples_per_pixel: u64) -> StratifiedSampler {
+        StratifiedSampler {
+            seed,
+            samples_per_pixel: samples_per_pixel.clamp(1, u32::MAX as u64) as u32,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0
+        }
+    }
+
+    // Stratum of the current sample among `n` and a hash for jittering inside it
+    fn stratum(&mut self, n: u32) -> (u32, u64) {
+        let h = hash(&[self.seed, self.pixel.0, self.pixel.1, self.dimension]);
+        let round = self.index / n as u64;
+        let stratum = permutation_element((self.index % n as u64) as u32, n, mix(h ^ round) as u32);
+        (stratum, hash(&[h, self.index]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> FloatT {
+        let n = self.samples_per_pixel;
+        let (stratum, h) = self.stratum(n);
+        self.dimension += 1;
+        (stratum as FloatT + to_unit(h)) / n as FloatT
+    }
+
+    fn get_2d(&mut self) -> (FloatT, FloatT) {
+        let nx = ((self.samples_per_pixel as FloatT).sqrt() as u32).max(1);
+        let ny = self.samples_per_pixel.div_ceil(nx);
+        let (stratum, h) = self.stratum(nx * ny);
+        self.dimension += 2;
+        ((((stratum % nx) as FloatT) + to_unit(h)) / nx as FloatT,
+         (((stratum / nx) as FloatT) + to_unit(mix(h))) / ny as FloatT)
+    }
+}
+
+const PRIMES: [u64; 32] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83,
+                           89, 97, 101, 103, 107, 109, 113, 127, 131];
+
+fn radical_inverse(base: u64, mut a: u64) -> FloatT {
+    let inv_base = 1.0 / base as FloatT;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0;
+    while a > 0 {
+        let next = a / base;
+        reversed = reversed * base + (a - next * base);
+        inv_base_n *= inv_base;
+        a = next;
+    }
+    (reversed as FloatT * inv_base_n).min(1.0 - FloatT::EPSILON)
+}
+
+/// The Halton sequence with a prime base per dimension, decorrelated between pixels with a
+/// random toroidal shift. Dimensions past the 32nd reuse the bases with a different shift.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: (u64, u64),
+    index: u64,
+    dimension: u64
+}
+
+impl HaltonSampler {
+    /// Creates a sampler seeded with `seed`.
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0
+        }
+    }
+
+    fn next(&mut self) -> FloatT {
+        let base = PRIMES[(self.dimension % PRIMES.len() as u64) as usize];
+        let shift = to_unit(hash(&[self.seed, self.pixel.0, self.pixel.1, self.dimension]));
+        self.dimension += 1;
+        let v = radical_inverse(base, self.index) + shift;
+        if v >= 1.0 { v - 1.0 } else { v }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> FloatT {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (FloatT, FloatT) {
+        (self.next(), self.next())
+    }
+}
+
+// First two Sobol dimensions as 32-bit fractions
+fn sobol(mut index: u32, dimension: u32) -> u32 {
+    let mut result = 0;
+    let mut v = 1u32 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        // Dimension 0 is van der Corput, dimension 1 uses the x + 1 polynomial
+        v = if dimension == 0 { v >> 1 } else { v ^ (v >> 1) };
+    }
+    result
+}
+
+// Laine and Karras' hash based permutation, it only lets lower bits affect higher ones
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+// Owen scrambling as in Burley's "Practical Hash-based Owen Scrambling"
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Owen-scrambled Sobol point, the index is shuffled first so every dimension is decorrelated
+fn sobol_owen(index: u64, h: u64, dimension: u32) -> u32 {
+    let index = nested_uniform_scramble(index as u32, h as u32);
+    nested_uniform_scramble(sobol(index, dimension), mix(h ^ dimension as u64) as u32)
+}
+
+/// Owen-scrambled Sobol points. Every 1D or 2D request is padded from its own independently
+/// scrambled copy of the first two Sobol dimensions, so sample counts that are powers of two
+/// are perfectly stratified in every pair of dimensions.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: (u64, u64),
+    index: u64,
+    dimension: u64
+}
+
+impl SobolSampler {
+    /// Creates a sampler seeded with `seed`.
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0
+        }
+    }
+
+    fn hash(&mut self) -> u64 {
+        let h = hash(&[self.seed, self.pixel.0, self.pixel.1, self.dimension]);
+        self.dimension += 1;
+        h
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> FloatT {
+        let h = self.hash();
+        u32_to_unit(sobol_owen(self.index, h, 0))
+    }
+
+    fn get_2d(&mut self) -> (FloatT, FloatT) {
+        let h = self.hash();
+        (u32_to_unit(sobol_owen(self.index, h, 0)), u32_to_unit(sobol_owen(self.index, h, 1)))
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+const BLUE_NOISE_SIGMA: FloatT = 1.5;
+
+static BLUE_NOISE: OnceLock<Vec<FloatT>> = OnceLock::new();
+
+// Tileable blue noise threshold mask made with Ulichney's void-and-cluster method
+fn void_and_cluster() -> Vec<FloatT> {
+    const N: usize = BLUE_NOISE_SIZE;
+
+    // Gaussian falloff for every toroidal offset
+    let kernel: Vec<FloatT> = (0..N * N).map(|k| {
+        let (dx, dy) = (k % N, k / N);
+        let (dx, dy) = (dx.min(N - dx) as FloatT, dy.min(N - dy) as FloatT);
+        (-(dx * dx + dy * dy) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+    }).collect();
+    let update = |energy: &mut [FloatT], p: usize, sign: FloatT| {
+        let (px, py) = (p % N, p / N);
+        for y in 0..N {
+            let row = ((y + N - py) % N) * N;
+            for x in 0..N {
+                energy[y * N + x] += sign * kernel[row + (x + N - px) % N];
+            }
+        }
+    };
+    // The most crowded set pixel and the emptiest unset pixel
+    let tightest_cluster = |pattern: &[bool], energy: &[FloatT]| {
+        (0..N * N).filter(|&p| pattern[p]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[FloatT]| {
+        (0..N * N).filter(|&p| !pattern[p]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut pattern = vec![false; N * N];
+    let mut energy = vec![0.0; N * N];
+    let initial = N * N / 10;
+    let mut count = 0;
+    while count < initial {
+        let p = rng.gen_range(0..N * N);
+        if !pattern[p] {
+            pattern[p] = true;
+            update(&mut energy, p, 1.0);
+            count += 1;
+        }
+    }
+
+    // Spread the initial points out by moving them from clusters into voids until stable
+    loop {
+        let c = tightest_cluster(&pattern, &energy);
+        pattern[c] = false;
+        update(&mut energy, c, -1.0);
+        let v = largest_void(&pattern, &energy);
+        pattern[v] = true;
+        update(&mut energy, v, 1.0);
+        if v == c {
+            break;
+        }
+    }
+
+    // Rank the initial points by removing clusters, then the rest by filling voids
+    let mut rank = vec![0; N * N];
+    let (mut removed, mut removed_energy) = (pattern.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let c = tightest_cluster(&removed, &removed_energy);
+        removed[c] = false;
+        update(&mut removed_energy, c, -1.0);
+        rank[c] = r;
+    }
+    for r in initial..N * N {
+        let v = largest_void(&pattern, &energy);
+        pattern[v] = true;
+        update(&mut energy, v, 1.0);
+        rank[v] = r;
+    }
+    rank.into_iter().map(|r| (r as FloatT + 0.5) / (N * N) as FloatT).collect()
+}
+
+/// Owen-scrambled Sobol points shared by every pixel, each pixel shifted by a blue noise mask.
+/// The mask is offset differently for every dimension.
+pub struct BlueNoiseSampler {
+    seed: u64,
+    pixel: (u64, u64),
+    index: u64,
+    dimension: u64,
+    mask: &'static [FloatT]
+}
+
+impl BlueNoiseSampler {
+    /// Creates a sampler seeded with `seed`. The first one builds the shared mask.
+    pub fn new(seed: u64) -> BlueNoiseSampler {
+        BlueNoiseSampler {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            mask: BLUE_NOISE.get_or_init(void_and_cluster)
+        }
+    }
+
+    fn shift(&self, h: u64) -> FloatT {
+        let n = BLUE_NOISE_SIZE as u64;
+        let (x, y) = ((self.pixel.0 + h % n) % n, (self.pixel.1 + (h >> 32) % n) % n);
+        self.mask[(y * n + x) as usize]
+    }
+
+    fn next(&mut self, h: u64, dimension: u32) -> FloatT {
+        let v = u32_to_unit(sobol_owen(self.index, h, dimension)) + self.shift(mix(h ^ dimension as u64));
+        if v >= 1.0 { v - 1.0 } else { v }
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> FloatT {
+        // The sequence only depends on the dimension, the pixel only picks the mask entry
+        let h = hash(&[self.seed, self.dimension]);
+        self.dimension += 1;
+        self.next(h, 0)
+    }
+
+    fn get_2d(&mut self) -> (FloatT, FloatT) {
+        let h = hash(&[self.seed, self.dimension]);
+        self.dimension += 1;
+        (self.next(h, 0), self.next(h, 1))
+    }
+}
+
+/// Maps a point in `[0, 1)²` to a uniformly distributed unit vector.
+pub fn sample_unit_sphere(u: (FloatT, FloatT)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Maps a point in `[0, 1)²` and a radius sample to a uniformly distributed point inside the unit sphere.
+pub fn sample_unit_ball(u: (FloatT, FloatT), r: FloatT) -> Vec3 {
+    r.cbrt() * sample_unit_sphere(u)
+}
+
+/// Maps a point in `[0, 1)²` to a uniformly distributed unit vector within the cone around +z
+/// whose half angle has cosine `cos_max`.
+pub fn sample_cone(u: (FloatT, FloatT), cos_max: FloatT) -> Vec3 {
+    let z = 1.0 - u.0 * (1.0 - cos_max);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Maps a point in `[0, 1)²` to a uniformly distributed point on the unit disc in the xy plane,
+/// using Shirley's concentric mapping so nearby samples stay nearby.
+pub fn sample_unit_disc(u: (FloatT, FloatT)) -> Vec3 {
+    let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::default();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// Maps a point in `[0, 1)²` to a uniformly distributed point inside the regular polygon with
+/// `sides` corners on the unit circle in the xy plane, the first `rotation` radians from +x.
+pub fn sample_polygon(u: (FloatT, FloatT), sides: u32, rotation: FloatT) -> Vec3 {
+    // Pick one of the equal triangles fanning out from the center, then a point inside it
+    let scaled = u.0 * sides as FloatT;
+    let i = (scaled as u32).min(sides - 1);
+    let (a, b) = ((scaled - i as FloatT).sqrt(), u.1);
+    let corner = |k: u32| {
+        let phi = rotation + 2.0 * PI * k as FloatT / sides as FloatT;
+        Vec3::new(phi.cos(), phi.sin(), 0.0)
+    };
+    a * (1.0 - b) * corner(i) + a * b * corner(i + 1)
+}
+
+// Piecewise constant density over [0, 1) with one piece per value
+pub(crate) struct Distribution1D {
+    func: Vec<FloatT>,
+    cdf: Vec<FloatT>,
+    pub(crate) integral: FloatT
+}
+
+impl Distribution1D {
+    pub(crate) fn new(func: Vec<FloatT>) -> Distribution1D {
+        let n = func.len() as FloatT;
+        let mut cdf = vec![0.0; func.len() + 1];
+        for i in 0..func.len() {
+            cdf[i + 1] = cdf[i] + func[i] / n;
+        }
+        let integral = cdf[func.len()];
+        // An all black function is sampled uniformly instead
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 { *c / integral } else { i as FloatT / n };
+        }
+        Distribution1D { func, cdf, integral }
+    }
+
+    // Maps `u` to a position in [0, 1), returning it with the piece it's in and the density there
+    pub(crate) fn sample(&self, u: FloatT) -> (FloatT, usize, FloatT) {
+        let i = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.func.len() - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+        ((i as FloatT + du) / self.func.len() as FloatT, i, self.density(i))
+    }
+
+    pub(crate) fn density(&self, i: usize) -> FloatT {
+        if self.integral > 0.0 { self.func[i] / self.integral } else { 1.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPES: [SamplerType; 5] = [SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton,
+                                     SamplerType::Sobol, SamplerType::BlueNoise];
+
+    // Samples `first..first + count` of a pixel, each a 1D dimension followed by a 2D one, repeated `rounds` times
+    fn draw(sampler: &mut dyn Sampler, pixel: (u64, u64), first: u64, count: u64, rounds: usize)
+            -> Vec<Vec<(FloatT, FloatT)>> {
+        let mut dims = vec![Vec::new(); 2 * rounds];
+        for i in first..first + count {
+            sampler.start_pixel_sample(pixel.0, pixel.1, i);
+            for r in 0..rounds {
+                dims[2 * r].push((sampler.get_1d(), 0.0));
+                dims[2 * r + 1].push(sampler.get_2d());
+            }
+        }
+        dims
+    }
+
+    // Checks `points` land one per cell of an `nx` by `ny` grid
+    fn assert_stratified(points: &[(FloatT, FloatT)], nx: usize, ny: usize) {
+        let mut hits = vec![0; nx * ny];
+        for &(u, v) in points {
+            hits[(v * ny as FloatT) as usize * nx + (u * nx as FloatT) as usize] += 1;
+        }
+        assert!(hits.iter().all(|&h| h == 1), "{}x{} strata hit {:?}", nx, ny, hits);
+    }
+
+    #[test]
+    fn values_are_in_range_and_repeatable() {
+        for t in TYPES {
+            let first = draw(t.create(7, 16).as_mut(), (3, 9), 0, 16, 20);
+            for dim in &first {
+                assert!(dim.iter().all(|&(u, v)| (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v)), "{:?}", t);
+            }
+            // A fresh sampler, or the same one revisiting the pixel, gives the same values
+            let mut sampler = t.create(7, 16);
+            draw(sampler.as_mut(), (4, 9), 0, 16, 20);
+            assert_eq!(draw(sampler.as_mut(), (3, 9), 0, 16, 20), first, "{:?}", t);
+            assert_ne!(draw(t.create(8, 16).as_mut(), (3, 9), 0, 16, 20), first, "{:?}", t);
+        }
+    }
+
+    #[test]
+    fn stratified_one_sample_per_stratum() {
+        for (spp, nx, ny) in [(16, 4, 4), (12, 3, 4), (7, 2, 4), (1, 1, 1)] {
+            let mut sampler = StratifiedSampler::new(1, spp);
+            // Every round of `spp` samples covers all the strata again
+            for first in [0, spp, 5 * spp] {
+                let dims = draw(&mut sampler, (10, 20), first, spp, 3);
+                for r in 0..3 {
+                    assert_stratified(&dims[2 * r], spp as usize, 1);
+                    if nx * ny == spp as usize {
+                        assert_stratified(&dims[2 * r + 1], nx, ny);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_stratifies_powers_of_two() {
+        for pixel in [(5, 6), (0, 0)] {
+            let dims = draw(&mut SobolSampler::new(2), pixel, 0, 16, 4);
+            for r in 0..4 {
+                assert_stratified(&dims[2 * r], 16, 1);
+                // Every elementary interval of area 1/16
+                for (nx, ny) in [(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+                    assert_stratified(&dims[2 * r + 1], nx, ny);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn halton_stratifies_by_base() {
+        let dims = draw(&mut HaltonSampler::new(3), (1, 2), 0, 6, 1);
+        assert_stratified(&dims[0][..2], 2, 1);
+        assert_stratified(&dims[0][..4], 4, 1);
+        let second: Vec<_> = dims[1].iter().map(|&(u, _)| (u, 0.0)).collect();
+        let third: Vec<_> = dims[1].iter().map(|&(_, v)| (v, 0.0)).collect();
+        assert_stratified(&second[..3], 3, 1);
+        assert_stratified(&third[..5], 5, 1);
+    }
+
+    #[test]
+    fn radical_inverses() {
+        assert_eq!(radical_inverse(2, 0), 0.0);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn permutations_are_bijective() {
+        for l in [1, 2, 3, 16, 17, 100] {
+            for p in [0, 1, 0xdeadbeef] {
+                let mut seen = vec![false; l as usize];
+                for i in 0..l {
+                    seen[permutation_element(i, l, p) as usize] = true;
+                }
+                assert!(seen.iter().all(|&s| s), "l {} p {}", l, p);
+            }
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask_ranks_every_pixel_once() {
+        let n = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+        let mut ranks: Vec<usize> = BLUE_NOISE.get_or_init(void_and_cluster).iter()
+            .map(|&v| (v * n as FloatT) as usize).collect();
+        ranks.sort();
+        assert!(ranks.into_iter().eq(0..n));
+    }
+}
@@ -0,0 +1,741 @@
+use super::vec::{Vec3, Point3, Color, FloatT};
+use super::hit::World;
+use super::sphere::Sphere;
+use super::rect::Rect;
+use super::camera::{Camera, PerspectiveCamera, OrthographicCamera, FisheyeCamera, EquirectangularCamera, CubemapCamera,
+                     ApertureShape, ApertureMask};
+use super::material::{BSDF, Lambertian, Metal, Dielectric, DiffuseLight};
+use super::light::{Light, PointLight, SpotLight, DirectionalLight, AreaLight, EnvironmentLight, Lights};
+use super::environment::{Environment, Gradient, EnvironmentMap, PreethamSky, DEFAULT_SKY_BOTTOM, DEFAULT_SKY_TOP, SUN_ANGLE};
+use super::input;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Spotlight cone angles from the axis in degrees, full intensity inside the first
+const DEFAULT_SPOT_INNER: FloatT = 30.0;
+const DEFAULT_SPOT_OUTER: FloatT = 45.0;
+const DEFAULT_SUN_ELEVATION: FloatT = 45.0;
+const DEFAULT_TURBIDITY: FloatT = 3.0;
+const DEFAULT_SUN_LUMINOSITY: FloatT = 3.0;
+const DEFAULT_FOV: FloatT = 90.0;
+const DEFAULT_FISHEYE_FOV: FloatT = 180.0;
+
+// Scene files are line based. Each line is a directive followed by key=value pairs,
+// vectors are written as comma separated components and '#' starts a comment:
+//
+//   image width=1200 height=675 spp=100 depth=9
+//   camera lookfrom=13,2,3 lookat=0,0,0 vup=0,1,0 fov=20 aperture=0.1 focus=10
+//   camera lookfrom=13,2,3 lookat=0,0,0 fov=20 aperture=0.3 focus_pixel=600,340 blades=6 rotation=15
+//   material ground lambertian albedo=0.5,0.5,0.5
+//   sphere center=0,-1000,0 radius=1000 material=ground
+//   material lamp diffuse_light color=1,1,1 intensity=15
+//   rect corner=-1,4,-1 u=2,0,0 v=0,0,2 material=lamp
+//   light position=0,15,0 color=1,1,1 luminosity=1 radius=0.5
+//   light spot position=0,5,0 direction=0,-1,0 luminosity=20 inner=20 outer=30
+//   light directional direction=-1,-2,-1 luminosity=3 angle=0.53
+//   environment map file=sky.hdr rotation=90 intensity=1
+//   environment sky elevation=30 azimuth=120 turbidity=3 sun=3
+//
+// Files are found relative to the scene file. Without an environment directive the sky is the
+// default gradient. The physical sky also adds a sun of the given luminosity, or none for sun=0.
+// A camera with an aperture can focus on whatever is under focus_pixel, and its out of focus
+// highlights take the shape of its blades or of a bokeh=<image> mask. Cameras project with
+// projection=perspective (the default), orthographic with size=<height>, fisheye with fov=<angle>,
+// equirect for a 360° panorama or cubemap for six faces side by side; only perspective has a lens.
+
+/// Output image settings from the `image` directive.
+pub struct ImageSettings {
+    /// Width in pixels.
+    pub width: u64,
+    /// Height in pixels.
+    pub height: u64,
+    /// Camera rays per pixel.
+    pub samples_per_pixel: u64,
+    /// Maximum bounces per path.
+    pub max_depth: u64
+}
+
+/// How a camera maps directions onto the image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    /// A pinhole or thin lens seeing `fov` degrees vertically.
+    Perspective,
+    /// Parallel rays covering a view `size` units tall.
+    Orthographic {
+        /// Height of the view in world units.
+        size: FloatT
+    },
+    /// An equidistant fisheye seeing `fov` degrees across its image circle.
+    Fisheye,
+    /// A 360° panorama.
+    Equirectangular,
+    /// Six cube faces side by side.
+    Cubemap
+}
+
+/// Camera placement from the `camera` directive.
+pub struct CameraSettings {
+    /// How directions are mapped onto the image.
+    pub projection: Projection,
+    /// Camera position.
+    pub lookfrom: Point3,
+    /// Point the camera faces.
+    pub lookat: Point3,
+    /// Up direction.
+    pub vup: Vec3,
+    /// Vertical field of view in degrees, or the angle across a fisheye's image circle.
+    pub fov: FloatT,
+    /// Lens diameter.
+    pub aperture: FloatT,
+    /// Distance to the plane in focus.
+    pub focus_dist: FloatT,
+    /// Shape of the lens opening.
+    pub aperture_shape: ApertureShape,
+    /// Point of the image to focus on instead of `focus_dist`, as fractions of the width and
+    /// height from the top left.
+    pub focus_point: Option<(FloatT, FloatT)>
+}
+
+impl CameraSettings {
+    /// Creates the camera for an image with the given width over height.
+    pub fn build(&self, aspect_ratio: FloatT) -> Box<dyn Camera> {
+        match self.projection {
+            Projection::Perspective => {
+                let mut camera = PerspectiveCamera::new(self.lookfrom,
+                                                        self.lookat,
+                                                        self.vup,
+                                                        self.fov,
+                                                        aspect_ratio,
+                                                        self.aperture,
+                                                        self.focus_dist);
+                camera.set_aperture_shape(self.aperture_shape.clone());
+                Box::new(camera)
+            }
+            Projection::Orthographic { size } =>
+                Box::new(OrthographicCamera::new(self.lookfrom, self.lookat, self.vup, size, aspect_ratio)),
+            Projection::Fisheye =>
+                Box::new(FisheyeCamera::new(self.lookfrom, self.lookat, self.vup, self.fov, aspect_ratio)),
+            Projection::Equirectangular => Box::new(EquirectangularCamera::new(self.lookfrom, self.lookat, self.vup)),
+            Projection::Cubemap => Box::new(CubemapCamera::new(self.lookfrom, self.lookat, self.vup))
+        }
+    }
+}
+
+/// A parsed scene file.
+pub struct Scene {
+    /// Every object in the scene.
+    pub world: World,
+    /// Every light in the scene.
+    pub lights: Lights,
+    /// Where the scene is viewed from.
+    pub camera: CameraSettings,
+    /// Image size and quality.
+    pub image: ImageSettings
+}
+
+#[derive(Debug)]
+/// Errors from loading a scene.
+pub enum SceneError {
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The file is malformed, positions are 1-based.
+    Parse {
+        /// Line of the error.
+        line: usize,
+        /// Column of the error.
+        col: usize,
+        /// What went wrong.
+        msg: String
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{}", e),
+            SceneError::Parse { line, col, msg } => write!(f, "{}:{}: {}", line, col, msg)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> SceneError {
+        SceneError::Io(e)
+    }
+}
+
+fn error<T>(line: usize, col: usize, msg: String) -> Result<T, SceneError> {
+    Err(SceneError::Parse { line, col, msg })
+}
+
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    col: usize
+}
+
+struct Param<'a> {
+    key: Token<'a>,
+    value: Token<'a>,
+    used: bool
+}
+
+// The key=value pairs following a directive
+struct Params<'a> {
+    directive: Token<'a>,
+    params: Vec<Param<'a>>
+}
+
+impl<'a> Params<'a> {
+    fn new(directive: Token<'a>, tokens: Vec<Token<'a>>) -> Result<Params<'a>, SceneError> {
+        let mut params: Vec<Param<'a>> = Vec::new();
+
+        for tok in tokens {
+            let Some((key, value)) = tok.text.split_once('=') else {
+                return error(tok.line, tok.col, format!("expected key=value, found '{}'", tok.text));
+            };
+            if key.is_empty() || value.is_empty() {
+                return error(tok.line, tok.col, format!("expected key=value, found '{}'", tok.text));
+            }
+            if let Some(p) = params.iter().find(|p| p.key.text == key) {
+                return error(tok.line, tok.col, format!("'{}' was already given at column {}", key, p.key.col));
+            }
+
+            params.push(Param {
+                key: Token { text: key, line: tok.line, col: tok.col },
+                value: Token { text: value, line: tok.line, col: tok.col + key.chars().count() + 1 },
+                used: false
+            });
+        }
+        Ok(Params { directive, params })
+    }
+
+    fn take(&mut self, key: &str) -> Option<&Token<'a>> {
+        let p = self.params.iter_mut().find(|p| p.key.text == key)?;
+        p.used = true;
+        Some(&p.value)
+    }
+
+    fn missing<T>(&self, key: &str) -> Result<T, SceneError> {
+        error(self.directive.line, self.directive.col,
+              format!("'{}' requires '{}'", self.directive.text, key))
+    }
+
+    fn float(&mut self, key: &str) -> Result<Option<FloatT>, SceneError> {
+        match self.take(key) {
+            Some(tok) => parse_float(tok).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn uint(&mut self, key: &str) -> Result<Option<u64>, SceneError> {
+        match self.take(key) {
+            Some(tok) => match tok.text.parse::<u64>() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => error(tok.line, tok.col, format!("expected a non-negative integer, found '{}'", tok.text))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn vec3(&mut self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        let Some(tok) = self.take(key) else {
+            return Ok(None);
+        };
+
+        let mut e = [0.0; 3];
+        let mut n = 0;
+        let mut col = tok.col;
+        for part in tok.text.split(',') {
+            if n == 3 {
+                return error(tok.line, col, format!("expected 3 components, found '{}'", tok.text));
+            }
+            e[n] = parse_float(&Token { text: part, line: tok.line, col })?;
+            col += part.chars().count() + 1;
+            n += 1;
+        }
+        if n != 3 {
+            return error(tok.line, tok.col, format!("expected 3 components, found '{}'", tok.text));
+        }
+        Ok(Some(Vec3::new(e[0], e[1], e[2])))
+    }
+
+    // Pixel coordinates written as x,y
+    fn pixel(&mut self, key: &str) -> Result<Option<(u64, u64)>, SceneError> {
+        let Some(tok) = self.take(key) else {
+            return Ok(None);
+        };
+        let parsed = tok.text.split_once(',').and_then(|(x, y)| Some((x.parse::<u64>().ok()?, y.parse::<u64>().ok()?)));
+        match parsed {
+            Some(p) => Ok(Some(p)),
+            None => error(tok.line, tok.col, format!("expected a pixel as x,y, found '{}'", tok.text))
+        }
+    }
+
+    // Line and column where `key` was given
+    fn position(&self, key: &str) -> Option<(usize, usize)> {
+        self.params.iter().find(|p| p.key.text == key).map(|p| (p.key.line, p.key.col))
+    }
+
+    fn string(&mut self, key: &str) -> Option<&Token<'a>> {
+        self.take(key)
+    }
+
+    // Looks up the material named by 'material', returning its name too
+    fn material(&mut self, materials: &HashMap<&str, Arc<dyn BSDF>>) -> Result<(&'a str, Arc<dyn BSDF>), SceneError> {
+        match self.string("material") {
+            Some(tok) => match materials.get(tok.text) {
+                Some(m) => Ok((tok.text, m.clone())),
+                None => error(tok.line, tok.col, format!("undefined material '{}'", tok.text))
+            },
+            None => self.missing("material")
+        }
+    }
+
+    fn req_float(&mut self, key: &str) -> Result<FloatT, SceneError> {
+        self.float(key)?.map_or_else(|| self.missing(key), Ok)
+    }
+
+    fn req_vec3(&mut self, key: &str) -> Result<Vec3, SceneError> {
+        self.vec3(key)?.map_or_else(|| self.missing(key), Ok)
+    }
+
+    // Reject any keys the directive didn't ask for
+    fn finish(self) -> Result<(), SceneError> {
+        match self.params.iter().find(|p| !p.used) {
+            Some(p) => error(p.key.line, p.key.col,
+                             format!("unknown key '{}' for '{}'", p.key.text, self.directive.text)),
+            None => Ok(())
+        }
+    }
+}
+
+fn parse_float(tok: &Token) -> Result<FloatT, SceneError> {
+    match tok.text.parse::<FloatT>() {
+        Ok(v) if v.is_finite() => Ok(v),
+        _ => error(tok.line, tok.col, format!("expected a number, found '{}'", tok.text))
+    }
+}
+
+fn tokenize(line: &str, line_no: usize) -> Vec<Token<'_>> {
+    let line = line.split('#').next().unwrap_or("");
+    let mut tokens = Vec::new();
+    let mut start: Option<(usize, usize)> = None;
+
+    for (col, (i, c)) in line.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((i, col + 1)),
+            (true, Some((s, scol))) => {
+                tokens.push(Token { text: &line[s..i], line: line_no, col: scol });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((s, scol)) = start {
+        tokens.push(Token { text: &line[s..], line: line_no, col: scol });
+    }
+    tokens
+}
+
+impl Scene {
+    /// Reads and parses the scene file at `path`.
+    pub fn load(path: &str) -> Result<Scene, SceneError> {
+        let src = fs::read_to_string(path)?;
+        Scene::parse_in(&src, Path::new(path).parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parses a scene description, finding the files it names relative to the working directory.
+    pub fn parse(src: &str) -> Result<Scene, SceneError> {
+        Scene::parse_in(src, Path::new(""))
+    }
+
+    fn parse_in(src: &str, dir: &Path) -> Result<Scene, SceneError> {
+        let mut world = World::new();
+        let mut lights = Lights::new();
+        let mut materials: HashMap<&str, Arc<dyn BSDF>> = HashMap::new();
+        // Radiance of the emissive materials, objects using them become area lights
+        let mut emissive: HashMap<&str, Color> = HashMap::new();
+        let mut camera: Option<CameraSettings> = None;
+        // Checked against the image size once the whole file is read, so kept with where it was given
+        let mut focus_pixel: Option<((u64, u64), (usize, usize))> = None;
+        let mut environment: Option<Arc<dyn Environment>> = None;
+        let mut image = ImageSettings {
+            width: 768,
+            height: 0,
+            samples_per_pixel: 100,
+            max_depth: 16
+        };
+        let mut aspect_ratio: FloatT = 16.0 / 9.0;
+        let mut last_line = 0;
+
+        for (i, line) in src.lines().enumerate() {
+            last_line = i + 1;
+            let mut tokens = tokenize(line, i + 1).into_iter();
+            let Some(directive) = tokens.next() else {
+                continue;
+            };
+
+            match directive.text {
+                "image" => {
+                    let mut p = Params::new(directive, tokens.collect())?;
+                    image.width = p.uint("width")?.unwrap_or(image.width);
+                    image.height = p.uint("height")?.unwrap_or(image.height);
+                    aspect_ratio = p.float("aspect")?.unwrap_or(aspect_ratio);
+                    image.samples_per_pixel = p.uint("spp")?.unwrap_or(image.samples_per_pixel);
+                    image.max_depth = p.uint("depth")?.unwrap_or(image.max_depth);
+                    p.finish()?;
+                }
+                "camera" => {
+                    if camera.is_some() {
+                        return error(directive.line, directive.col, "camera is already defined".to_string());
+                    }
+                    let (line, col) = (directive.line, directive.col);
+                    let mut p = Params::new(directive, tokens.collect())?;
+                    let lookfrom = p.req_vec3("lookfrom")?;
+                    let lookat = p.req_vec3("lookat")?;
+                    let projection = match p.string("projection").map(|t| (t.text, t.line, t.col)) {
+                        None | Some(("perspective", _, _)) => Projection::Perspective,
+                        Some(("orthographic", _, _)) => Projection::Orthographic { size: p.req_float("size")? },
+                        Some(("fisheye", _, _)) => Projection::Fisheye,
+                        Some(("equirect", _, _)) => Projection::Equirectangular,
+                        Some(("cubemap", _, _)) => Projection::Cubemap,
+                        Some((text, line, col)) => return error(line, col, format!("unknown projection '{}'", text))
+                    };
+                    let fov = match projection {
+                        Projection::Perspective => p.float("fov")?.unwrap_or(DEFAULT_FOV),
+                        Projection::Fisheye => p.float("fov")?.unwrap_or(DEFAULT_FISHEYE_FOV),
+                        _ => DEFAULT_FOV
+                    };
+
+                    // Only the perspective camera has a lens, other projections reject its keys
+                    let mut aperture = 0.0;
+                    let mut focus_dist = (lookfrom - lookat).length();
+                    let mut aperture_shape = ApertureShape::Circle;
+                    focus_pixel = None;
+                    if projection == Projection::Perspective {
+                        aperture = p.float("aperture")?.unwrap_or(0.0);
+                        let focus = p.float("focus")?;
+                        let at = p.position("focus_pixel");
+                        focus_pixel = p.pixel("focus_pixel")?.zip(at);
+                        if focus.is_some() && focus_pixel.is_some() {
+                            return error(line, col,
+                                         "'focus' and 'focus_pixel' can't both be given".to_string());
+                        }
+                        focus_dist = focus.unwrap_or(focus_dist);
+
+                        // Straight blades or an image mask instead of a round lens
+                        let blades = p.uint("blades")?;
+                        let rotation = p.float("rotation")?;
+                        if rotation.is_some() && blades.is_none() {
+                            return error(line, col, "'rotation' requires 'blades'".to_string());
+                        }
+                        aperture_shape = match (p.string("bokeh"), blades) {
+                            (Some(_), Some(_)) => return error(line, col,
+                                                               "'bokeh' and 'blades' can't both be given".to_string()),
+                            (Some(file), None) => {
+                                let mask = match input::load(&dir.join(file.text)) {
+                                    Ok(image) => ApertureMask::new(&image),
+                                    Err(e) => return error(file.line, file.col, format!("can't load '{}': {}", file.text, e))
+                                };
+                                if mask.is_closed() {
+                                    return error(file.line, file.col, format!("'{}' is black everywhere", file.text));
+                                }
+                                ApertureShape::Mask(Arc::new(mask))
+                            }
+                            (None, Some(blades)) => {
+                                if blades < 3 {
+                                    return error(line, col, "'blades' must be at least 3".to_string());
+                                }
+                                ApertureShape::Blades { blades: blades.min(u32::MAX as u64) as u32,
+                                                        rotation: rotation.unwrap_or(0.0) }
+                            }
+                            (None, None) => ApertureShape::Circle
+                        };
+                    }
+
+                    camera = Some(CameraSettings {
+                        projection,
+                        lookfrom,
+                        lookat,
+                        vup: p.vec3("vup")?.unwrap_or(Vec3::new(0.0, 1.0, 0.0)),
+                        fov,
+                        aperture,
+                        focus_dist,
+                        aperture_shape,
+                        focus_point: None
+                    });
+                    p.finish()?;
+                }
+                "material" => {
+                    let (Some(name), Some(kind)) = (tokens.next(), tokens.next()) else {
+                        return error(directive.line, directive.col,
+                                     "expected 'material <name> <type> ...'".to_string());
+                    };
+                    if materials.contains_key(name.text) {
+                        return error(name.line, name.col, format!("material '{}' is already defined", name.text));
+                    }
+
+                    let mut p = Params::new(directive, tokens.collect())?;
+                    let mat: Arc<dyn BSDF> = match kind.text {
+                        "lambertian" => Arc::new(Lambertian::new(p.req_vec3("albedo")?)),
+                        "metal" => Arc::new(Metal::new(p.req_vec3("albedo")?, p.float("fuzz")?.unwrap_or(0.0))),
+                        "dielectric" => Arc::new(Dielectric::new(p.req_float("ir")?)),
+                        "diffuse_light" => {
+                            let light = DiffuseLight::new(p.vec3("color")?.unwrap_or(Color::new(1.0, 1.0, 1.0)),
+                                                          p.float("intensity")?.unwrap_or(1.0));
+                            emissive.insert(name.text, light.radiance());
+                            Arc::new(light)
+                        }
+                        _ => return error(kind.line, kind.col, format!("unknown material type '{}'", kind.text))
+                    };
+                    p.finish()?;
+                    materials.insert(name.text, mat);
+                }
+                "sphere" => {
+                    let mut p = Params::new(directive, tokens.collect())?;
+                    let center = p.req_vec3("center")?;
+                    let radius = p.req_float("radius")?;
+                    let (name, mat) = p.material(&materials)?;
+                    p.finish()?;
+                    let sphere = Arc::new(Sphere::new(center, radius, mat));
+                    if let Some(&radiance) = emissive.get(name) {
+                        lights.push(Box::new(AreaLight::new(sphere.clone(), radiance)));
+                    }
+                    world.push(Box::new(sphere));
+                }
+                "rect" => {
+                    let (line, col) = (directive.line, directive.col);
+                    let mut p = Params::new(directive, tokens.collect())?;
+                    let corner = p.req_vec3("corner")?;
+                    let u = p.req_vec3("u")?;
+                    let v = p.req_vec3("v")?;
+                    let (name, mat) = p.material(&materials)?;
+                    p.finish()?;
+                    if u.cross(v).near_zero() {
+                        return error(line, col, "rect edges 'u' and 'v' must not be parallel".to_string());
+                    }
+                    let rect = Arc::new(Rect::new(corner, u, v, mat));
+                    if let Some(&radiance) = emissive.get(name) {
+                        lights.push(Box::new(AreaLight::new(rect.clone(), radiance)));
+                    }
+                    world.push(Box::new(rect));
+                }
+                "light" => {
+                    // The type may be left out for point lights
+                    let mut tokens = tokens.peekable();
+                    let kind = match tokens.peek() {
+                        Some(tok) if !tok.text.contains('=') => tokens.next(),
+                        _ => None
+                    };
+                    let (kind, kind_line, kind_col) = kind.map_or(("point", 0, 0), |k| (k.text, k.line, k.col));
+
+                    let mut p = Params::new(directive, tokens.collect())?;
+                    let color = p.vec3("color")?.unwrap_or(Color::new(1.0, 1.0, 1.0));
+                    let luminosity = p.float("luminosity")?.unwrap_or(1.0);
+                    let light: Box<dyn Light> = match kind {
+                        "point" => Box::new(PointLight::new(p.req_vec3("position")?,
+                                                            color,
+                                                            luminosity,
+                                                            p.float("radius")?.unwrap_or(0.0))),
+                        "spot" => Box::new(SpotLight::new(p.req_vec3("position")?,
+                                                          color,
+                                                          p.vec3("direction")?.unwrap_or(Vec3::new(0.0, -1.0, 0.0)),
+                                                          luminosity,
+                                                          p.float("inner")?.unwrap_or(DEFAULT_SPOT_INNER),
+                                                          p.float("outer")?.unwrap_or(DEFAULT_SPOT_OUTER))),
+                        "directional" => Box::new(DirectionalLight::new(p.req_vec3("direction")?,
+                                                                        color,
+                                                                        luminosity,
+                                                                        p.float("angle")?.unwrap_or(0.0))),
+                        _ => return error(kind_line, kind_col, format!("unknown light type '{}'", kind))
+                    };
+                    p.finish()?;
+                    lights.push(light);
+                }
+                "environment" => {
+                    let Some(kind) = tokens.next() else {
+                        return error(directive.line, directive.col,
+                                     "expected 'environment <type> ...'".to_string());
+                    };
+                    if environment.is_some() {
+                        return error(directive.line, directive.col, "environment is already defined".to_string());
+                    }
+
+                    let mut p = Params::new(directive, tokens.collect())?;
+                    let intensity = p.float("intensity")?.unwrap_or(1.0);
+                    environment = Some(match kind.text {
+                        "gradient" => Arc::new(Gradient::new(p.vec3("bottom")?.unwrap_or(DEFAULT_SKY_BOTTOM),
+                                                             p.vec3("top")?.unwrap_or(DEFAULT_SKY_TOP),
+                                                             intensity)),
+                        "map" => {
+                            let Some(file) = p.string("file") else {
+                                return p.missing("file");
+                            };
+                            let (line, col) = (file.line, file.col);
+                            let image = match input::load(&dir.join(file.text)) {
+                                Ok(image) => image,
+                                Err(e) => return error(line, col, format!("can't load '{}': {}", file.text, e))
+                            };
+                            Arc::new(EnvironmentMap::new(image, p.float("rotation")?.unwrap_or(0.0), intensity))
+                        }
+                        "sky" => {
+                            let sky = PreethamSky::new(p.float("elevation")?.unwrap_or(DEFAULT_SUN_ELEVATION),
+                                                       p.float("azimuth")?.unwrap_or(0.0),
+                                                       p.float("turbidity")?.unwrap_or(DEFAULT_TURBIDITY),
+                                                       intensity);
+                            // The sun is a separate light so its disc is sampled directly
+                            let sun = p.float("sun")?.unwrap_or(DEFAULT_SUN_LUMINOSITY);
+                            if sun > 0.0 {
+                                lights.push(Box::new(DirectionalLight::new((-1.0) * sky.sun_direction(),
+                                                                           sky.sun_color(), sun, SUN_ANGLE)));
+                            }
+                            Arc::new(sky)
+                        }
+                        _ => return error(kind.line, kind.col, format!("unknown environment type '{}'", kind.text))
+                    });
+                    p.finish()?;
+                }
+                _ => return error(directive.line, directive.col, format!("unknown directive '{}'", directive.text))
+            }
+        }
+
+        if image.height == 0 && aspect_ratio > 0.0 {
+            image.height = ((image.width as FloatT) / aspect_ratio) as u64;
+        }
+        if image.width == 0 || image.height == 0 {
+            return error(last_line + 1, 1, "image width and height must be positive".to_string());
+        }
+        let Some(mut camera) = camera else {
+            return error(last_line + 1, 1, "scene has no camera".to_string());
+        };
+        if let Some(((x, y), (line, col))) = focus_pixel {
+            if x >= image.width || y >= image.height {
+                return error(line, col, format!("focus_pixel {},{} is outside the image", x, y));
+            }
+            // Aim at the pixel's center
+            camera.focus_point = Some(((x as FloatT + 0.5) / image.width as FloatT,
+                                       (y as FloatT + 0.5) / image.height as FloatT));
+        }
+
+        let environment = environment.unwrap_or_else(|| Arc::new(Gradient::default()));
+        lights.push(Box::new(EnvironmentLight::new(environment)));
+
+        Ok(Scene { world, lights, camera, image })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "camera lookfrom=0,0,1 lookat=0,0,0";
+
+    fn parse_error(src: &str) -> (usize, usize, String) {
+        match Scene::parse(src) {
+            Err(SceneError::Parse { line, col, msg }) => (line, col, msg),
+            Err(SceneError::Io(e)) => panic!("unexpected I/O error {}", e),
+            Ok(_) => panic!("parsed without an error")
+        }
+    }
+
+    #[test]
+    fn parses_a_small_scene() {
+        let src = format!("# comment\nimage width=40 height=20 spp=4 depth=3\n{}\n\
+                           material m lambertian albedo=0.5,0.5,0.5\n\
+                           sphere center=0,0,-1 radius=0.5 material=m  # trailing comment\n\
+                           light position=0,5,0\n", CAMERA);
+        let scene = Scene::parse(&src).unwrap();
+        assert_eq!((scene.image.width, scene.image.height), (40, 20));
+        assert_eq!((scene.image.samples_per_pixel, scene.image.max_depth), (4, 3));
+        assert_eq!(scene.world.len(), 1);
+        // The point light and the default sky
+        assert_eq!(scene.lights.len(), 2);
+        assert_eq!(scene.camera.focus_dist, 1.0);
+    }
+
+    #[test]
+    fn aspect_sets_the_missing_height() {
+        let scene = Scene::parse(&format!("image width=200 aspect=2\n{}", CAMERA)).unwrap();
+        assert_eq!(scene.image.height, 100);
+    }
+
+    #[test]
+    fn unknown_directive() {
+        assert_eq!(parse_error("image width=1 height=1\n  box size=1"),
+                   (2, 3, "unknown directive 'box'".to_string()));
+    }
+
+    #[test]
+    fn bad_vector_component_points_at_the_component() {
+        let (line, col, msg) = parse_error("camera lookfrom=0,x,1 lookat=0,0,0");
+        assert_eq!((line, col), (1, 19));
+        assert_eq!(msg, "expected a number, found 'x'");
+    }
+
+    #[test]
+    fn wrong_component_count() {
+        let (line, col, _) = parse_error("camera lookfrom=0,0 lookat=0,0,0");
+        assert_eq!((line, col), (1, 17));
+    }
+
+    #[test]
+    fn missing_and_unknown_keys() {
+        assert_eq!(parse_error("camera lookat=0,0,0"), (1, 1, "'camera' requires 'lookfrom'".to_string()));
+        assert_eq!(parse_error(&format!("{} zoom=2", CAMERA)),
+                   (1, 36, "unknown key 'zoom' for 'camera'".to_string()));
+    }
+
+    #[test]
+    fn repeated_key() {
+        let (line, col, msg) = parse_error("image width=1 width=2");
+        assert_eq!((line, col), (1, 15));
+        assert_eq!(msg, "'width' was already given at column 7");
+    }
+
+    #[test]
+    fn token_without_a_value() {
+        assert_eq!(parse_error("image width"), (1, 7, "expected key=value, found 'width'".to_string()));
+    }
+
+    #[test]
+    fn undefined_and_redefined_materials() {
+        let (line, col, msg) = parse_error(&format!("{}\nsphere center=0,0,0 radius=1 material=gold", CAMERA));
+        assert_eq!((line, col), (2, 39));
+        assert_eq!(msg, "undefined material 'gold'");
+
+        let src = "material m lambertian albedo=1,1,1\nmaterial m metal albedo=1,1,1";
+        assert_eq!(parse_error(src), (2, 10, "material 'm' is already defined".to_string()));
+    }
+
+    #[test]
+    fn second_camera_is_rejected() {
+        let src = format!("{}\n  {}", CAMERA, CAMERA);
+        assert_eq!(parse_error(&src), (2, 3, "camera is already defined".to_string()));
+    }
+
+    #[test]
+    fn focus_pixel_outside_the_image_points_at_the_key() {
+        // The image size may come after the camera
+        let src = "camera lookfrom=0,0,1 lookat=0,0,0 aperture=0.1 focus_pixel=40,5\nimage width=40 height=20";
+        assert_eq!(parse_error(src), (1, 49, "focus_pixel 40,5 is outside the image".to_string()));
+    }
+
+    #[test]
+    fn missing_camera_is_reported_after_the_last_line() {
+        assert_eq!(parse_error("image width=1 height=1\n\n"), (3, 1, "scene has no camera".to_string()));
+    }
+
+    #[test]
+    fn errors_display_line_and_column() {
+        let e = Scene::parse("image width=-1").err().unwrap();
+        assert_eq!(e.to_string(), "1:13: expected a non-negative integer, found '-1'");
+    }
+}
@@ -1,28 +1,33 @@
 use super::vec::{Point3, Vec3, FloatT};
 use super::ray::Ray;
 use super::hit::{Hit, HitRecord};
-use super::material::Scatter;
+use super::material::BSDF;
+use super::aabb::AABB;
+use super::light::{Shape, sphere_cone, sample_sphere_cone};
+
+use std::f64::consts::PI;
 
 use std::sync::Arc;
 
+/// A sphere. A negative radius flips the normals, making a hollow shell.
 pub struct Sphere {
     center: Point3,
     radius: FloatT,
-    mat: Arc<dyn Scatter>
+    mat: Arc<dyn BSDF>
 }
 
 impl Sphere {
-    pub fn new(cen: Point3, r: FloatT, m: Arc<dyn Scatter>) -> Sphere {
+    /// Creates a sphere at `cen` with radius `r` and material `m`.
+    pub fn new(cen: Point3, r: FloatT, m: Arc<dyn BSDF>) -> Sphere {
         Sphere {
             center: cen,
             radius: r,
             mat: m
         }
     }
-}
 
-impl Hit for Sphere {
-    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> (bool, Option<HitRecord>) {
+    // Nearest ray parameter in `[t_min, t_max]` where `r` crosses the surface
+    fn root(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> Option<FloatT> {
         let oc = r.origin() - self.center;
         let a = r.direction().length().powi(2);
         let half_b = oc.dot(r.direction());
@@ -30,7 +35,7 @@ impl Hit for Sphere {
 
         let discriminant = half_b.powi(2) - a * c;
         if discriminant < 0.0 {
-            return (false, None);
+            return None;
         }
 
         // Find nearest root
@@ -39,9 +44,18 @@ impl Hit for Sphere {
         if root < t_min || t_max < root {
             root = (-half_b + sqrtd) / a;
             if root < t_min || t_max < root {
-                return (false, None);
+                return None;
             }
         }
+        Some(root)
+    }
+}
+
+impl Hit for Sphere {
+    fn hit(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> (bool, Option<HitRecord>) {
+        let Some(root) = self.root(r, t_min, t_max) else {
+            return (false, None);
+        };
 
         let mut rec = HitRecord {
             t: root,
@@ -55,4 +69,30 @@ impl Hit for Sphere {
         
         (true, Some(rec))
     }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        // Radius may be negative for hollow spheres
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        Some(AABB::new(self.center - r, self.center + r))
+    }
+
+    fn hit_any(&self, r: &Ray, t_min: FloatT, t_max: FloatT) -> bool {
+        self.root(r, t_min, t_max).is_some()
+    }
+}
+
+impl Shape for Sphere {
+    fn sample(&self, p: Point3, u: (FloatT, FloatT)) -> Option<(Point3, Vec3, FloatT)> {
+        // Only the cone of directions that hit the sphere is sampled, not its hidden far side
+        let (dir, dist, pdf) = sample_sphere_cone(self.center, self.radius.abs(), p, u)?;
+        let q = p + dist * dir;
+        Some((q, (q - self.center) / self.radius, pdf))
+    }
+
+    fn pdf(&self, p: Point3, _q: Point3) -> FloatT {
+        match sphere_cone(self.center, self.radius.abs(), p) {
+            Some((_, one_minus_cos)) => 1.0 / (2.0 * PI * one_minus_cos),
+            None => 0.0
+        }
+    }
 }
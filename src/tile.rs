@@ -0,0 +1,171 @@
+/// A rectangular block of pixels, with rows counted from the top of the image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Tile {
+    /// Column of the top-left pixel.
+    pub x: usize,
+    /// Row of the top-left pixel.
+    pub y: usize,
+    /// Width in pixels, smaller than the tile size along the right edge.
+    pub width: usize,
+    /// Height in pixels, smaller than the tile size along the bottom edge.
+    pub height: usize
+}
+
+/// Order in which tiles are handed out to render threads.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TileOrder {
+    /// Row by row from the top-left corner.
+    Scanline,
+    /// Outwards from the center of the image, which usually holds the subject.
+    Spiral,
+    /// Along a Hilbert curve, so consecutive tiles are usually neighbours; always for square
+    /// power-of-two grids.
+    Hilbert
+}
+
+// Tile grid coordinates along a spiral starting in the middle of the grid
+fn spiral(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    let mut cells = Vec::with_capacity(nx * ny);
+    let (mut x, mut y) = (((nx - 1) / 2) as i64, ((ny - 1) / 2) as i64);
+    // Right, down, left, up
+    let dirs = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut step = 1;
+    let mut dir = 0;
+
+    // The spiral grows until it has covered the larger dimension, skipping cells off the grid
+    let in_grid = |x: i64, y: i64| x >= 0 && y >= 0 && (x as usize) < nx && (y as usize) < ny;
+    cells.push((x as usize, y as usize));
+    while cells.len() < nx * ny {
+        for _ in 0..2 {
+            let (dx, dy) = dirs[dir];
+            for _ in 0..step {
+                x += dx;
+                y += dy;
+                if in_grid(x, y) {
+                    cells.push((x as usize, y as usize));
+                }
+            }
+            dir = (dir + 1) % 4;
+        }
+        step += 1;
+    }
+    cells
+}
+
+// Distance along a Hilbert curve filling an `n` by `n` grid, `n` a power of two
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as usize;
+        let ry = (y & s > 0) as usize;
+        d += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+/// Splits a `width` x `height` image into tiles of at most `size` x `size` pixels, in `order`.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    assert!(size > 0, "tile size must be positive");
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let (nx, ny) = (width.div_ceil(size), height.div_ceil(size));
+    let cells = match order {
+        TileOrder::Scanline => (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect(),
+        TileOrder::Spiral => spiral(nx, ny),
+        TileOrder::Hilbert => {
+            let n = nx.max(ny).next_power_of_two();
+            let mut cells: Vec<(usize, usize)> = (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect();
+            cells.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+            cells
+        }
+    };
+
+    cells.into_iter().map(|(tx, ty)| {
+        let (x, y) = (tx * size, ty * size);
+        Tile {
+            x,
+            y,
+            width: size.min(width - x),
+            height: size.min(height - y)
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    // Checks every pixel lies in exactly one tile and no tile is empty or past the image
+    fn assert_covers(width: usize, height: usize, size: usize, order: TileOrder) {
+        let mut hits = vec![0; width * height];
+        for t in tiles(width, height, size, order) {
+            assert!(t.width > 0 && t.height > 0 && t.width <= size && t.height <= size, "{:?}", t);
+            assert!(t.x + t.width <= width && t.y + t.height <= height, "{:?} outside {}x{}", t, width, height);
+            for y in t.y..t.y + t.height {
+                for x in t.x..t.x + t.width {
+                    hits[y * width + x] += 1;
+                }
+            }
+        }
+        assert!(hits.iter().all(|&h| h == 1), "{:?} {}x{} tile {} missed or overlapped pixels",
+                order, width, height, size);
+    }
+
+    #[test]
+    fn every_order_covers_the_image_once() {
+        let sizes = [(1, 1), (7, 5), (64, 64), (100, 37), (37, 100), (129, 3), (3, 129), (200, 150)];
+        for order in ORDERS {
+            for &(width, height) in &sizes {
+                for size in [1, 8, 16, 32, 256] {
+                    assert_covers(width, height, size, order);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_image_has_no_tiles() {
+        for order in ORDERS {
+            assert!(tiles(0, 10, 8, order).is_empty());
+            assert!(tiles(10, 0, 8, order).is_empty());
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_middle() {
+        let t = tiles(100, 60, 10, TileOrder::Spiral);
+        assert_eq!((t[0].x, t[0].y), (40, 20));
+        // The first ring surrounds the center tile
+        for tile in &t[1..9] {
+            assert!(tile.x.abs_diff(40) <= 10 && tile.y.abs_diff(20) <= 10, "{:?}", tile);
+        }
+    }
+
+    #[test]
+    fn hilbert_steps_between_neighbours() {
+        // Holds for any grid that is a square power of two
+        for n in [1, 2, 4, 8, 16] {
+            let t = tiles(n * 4, n * 4, 4, TileOrder::Hilbert);
+            assert_eq!((t[0].x, t[0].y), (0, 0));
+            for pair in t.windows(2) {
+                let step = pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y);
+                assert_eq!(step, 4, "{:?} to {:?}", pair[0], pair[1]);
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use super::vec::{Color, FloatT};
+
+// Hable's filmic curve parameters and the linear white point it's normalized to
+const HABLE_A: FloatT = 0.15;
+const HABLE_B: FloatT = 0.50;
+const HABLE_C: FloatT = 0.10;
+const HABLE_D: FloatT = 0.20;
+const HABLE_E: FloatT = 0.02;
+const HABLE_F: FloatT = 0.30;
+const HABLE_WHITE: FloatT = 11.2;
+const HABLE_EXPOSURE_BIAS: FloatT = 2.0;
+
+/// Curve used to compress high dynamic range radiance into the displayable `[0, 1]` range.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMapOperator {
+    /// Clips every channel at 1.
+    Clamp,
+    /// `L / (1 + L)` on luminance, never quite reaches white.
+    Reinhard,
+    /// Reinhard with a luminance that maps to pure white.
+    ExtendedReinhard {
+        /// Smallest luminance that is displayed as white.
+        white: FloatT
+    },
+    /// Narkowicz's fit of the ACES filmic curve, applied per channel.
+    ACES,
+    /// John Hable's filmic curve from Uncharted 2, applied per channel.
+    Uncharted2
+}
+
+/// Converts linear radiance into display colors: scales by the exposure, applies the
+/// operator, then encodes with the sRGB transfer curve unless `srgb` is off.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ToneMap {
+    /// The tone curve.
+    pub operator: ToneMapOperator,
+    /// Exposure adjustment in stops, each stop doubles the brightness.
+    pub exposure: FloatT,
+    /// Encode with the sRGB transfer curve, off for images that already hold display values.
+    pub srgb: bool
+}
+
+impl Default for ToneMap {
+    fn default() -> ToneMap {
+        ToneMap::new(ToneMapOperator::Clamp)
+    }
+}
+
+fn hable(x: FloatT) -> FloatT {
+    ((x * (HABLE_A * x + HABLE_C * HABLE_B) + HABLE_D * HABLE_E) /
+     (x * (HABLE_A * x + HABLE_B) + HABLE_D * HABLE_F)) - HABLE_E / HABLE_F
+}
+
+fn aces(x: FloatT) -> FloatT {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// Encodes a linear value in `[0, 1]` with the sRGB transfer curve.
+pub fn srgb_encode(v: FloatT) -> FloatT {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ToneMap {
+    /// Creates a tone map with no exposure adjustment.
+    pub fn new(operator: ToneMapOperator) -> ToneMap {
+        ToneMap {
+            operator,
+            exposure: 0.0,
+            srgb: true
+        }
+    }
+
+    /// Writes values in `[0, 1]` unchanged, for images that are already display colors such as
+    /// sample count heatmaps.
+    pub fn identity() -> ToneMap {
+        ToneMap {
+            srgb: false,
+            ..ToneMap::new(ToneMapOperator::Clamp)
+        }
+    }
+
+    fn encode(&self, v: FloatT) -> FloatT {
+        if self.srgb { srgb_encode(v) } else { v }
+    }
+
+    /// Maps linear radiance to linear display values in `[0, 1]`.
+    pub fn apply(&self, c: Color) -> Color {
+        let c = c * (2.0 as FloatT).powf(self.exposure);
+        // Negative and NaN samples would poison the curves below
+        let c = Color::new(c.x().max(0.0), c.y().max(0.0), c.z().max(0.0));
+
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => c,
+            ToneMapOperator::Reinhard => {
+                let l = c.luminance();
+                c / (1.0 + l)
+            }
+            ToneMapOperator::ExtendedReinhard { white } => {
+                let l = c.luminance();
+                c * (1.0 + l / (white * white)) / (1.0 + l)
+            }
+            ToneMapOperator::ACES => Color::new(aces(c.x()), aces(c.y()), aces(c.z())),
+            ToneMapOperator::Uncharted2 => {
+                let scale = 1.0 / hable(HABLE_WHITE);
+                let h = |v: FloatT| hable(HABLE_EXPOSURE_BIAS * v) * scale;
+                Color::new(h(c.x()), h(c.y()), h(c.z()))
+            }
+        };
+        Color::new(mapped.x().clamp(0.0, 1.0), mapped.y().clamp(0.0, 1.0), mapped.z().clamp(0.0, 1.0))
+    }
+
+    /// Tone maps and encodes a color to 8 bits per channel.
+    pub fn quantize(&self, c: Color) -> [u8; 3] {
+        let c = self.apply(c);
+        let q = |v: FloatT| (255.0 * self.encode(v)).round() as u8;
+        [q(c.x()), q(c.y()), q(c.z())]
+    }
+
+    /// Like [`ToneMap::quantize`] but to 16 bits per channel.
+    pub fn quantize16(&self, c: Color) -> [u16; 3] {
+        let c = self.apply(c);
+        let q = |v: FloatT| (65535.0 * self.encode(v)).round() as u16;
+        [q(c.x()), q(c.y()), q(c.z())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMapOperator; 5] = [ToneMapOperator::Clamp, ToneMapOperator::Reinhard,
+                                             ToneMapOperator::ExtendedReinhard { white: 4.0 },
+                                             ToneMapOperator::ACES, ToneMapOperator::Uncharted2];
+
+    fn gray(v: FloatT) -> Color {
+        Color::new(v, v, v)
+    }
+
+    #[test]
+    fn black_stays_black() {
+        for op in OPERATORS {
+            let t = ToneMap::new(op);
+            assert!(t.apply(gray(0.0)).x().abs() < 1e-12, "{:?}", op);
+            assert_eq!(t.quantize(gray(0.0)), [0; 3], "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn curves_are_monotonic() {
+        for op in OPERATORS {
+            let t = ToneMap::new(op);
+            let mut last = 0.0;
+            for i in 0..=2000 {
+                let v = t.apply(gray(i as FloatT * 0.01)).x();
+                assert!(v >= last && v <= 1.0, "{:?} not monotonic at {}", op, i as FloatT * 0.01);
+                last = v;
+            }
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        assert!((ToneMap::new(ToneMapOperator::Reinhard).apply(gray(1.0)).x() - 0.5).abs() < 1e-12);
+        // Gray has the same luminance as its channels, so white maps exactly to 1
+        let t = ToneMap::new(ToneMapOperator::ExtendedReinhard { white: 4.0 });
+        assert!((t.apply(gray(4.0)).x() - 1.0).abs() < 1e-12);
+        assert!(t.apply(gray(2.0)).x() < 1.0);
+        let t = ToneMap::new(ToneMapOperator::Uncharted2);
+        assert!((t.apply(gray(HABLE_WHITE / HABLE_EXPOSURE_BIAS)).x() - 1.0).abs() < 1e-12);
+        assert_eq!(ToneMap::new(ToneMapOperator::Clamp).apply(gray(3.0)).x(), 1.0);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let mut t = ToneMap::new(ToneMapOperator::Clamp);
+        t.exposure = 1.0;
+        assert_eq!(t.apply(gray(0.25)).x(), 0.5);
+        t.exposure = -2.0;
+        assert_eq!(t.apply(gray(0.5)).x(), 0.125);
+    }
+
+    #[test]
+    fn srgb_encoding() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-12);
+        // The linear and power segments meet at the breakpoint
+        let below = srgb_encode(0.0031308);
+        assert_eq!(below, 12.92 * 0.0031308);
+        assert!((srgb_encode(0.0031308 + 1e-12) - below).abs() < 1e-6);
+        assert!((srgb_encode(0.5) - 0.735357).abs() < 1e-6);
+    }
+}
@@ -1,46 +1,57 @@
 use std::ops::{Index, IndexMut, Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign, Range};
 use std::fmt;
 use std::fmt::Display;
-use rand::{Rng, thread_rng};
+use rand::Rng;
 
 
+/// Floating point type used for all geometry and color math.
 pub type FloatT = f64;
 
 #[derive(Default, Clone, Copy)]
+/// A three component vector of [`FloatT`].
 pub struct Vec3 {
     e: [FloatT; 3]
 }
 
+/// A position in space.
 pub type Point3 = Vec3;
+/// A linear RGB color.
 pub type Color = Vec3;
 
 impl Vec3 {
-    pub fn new(e0: FloatT, e1: FloatT, e2: FloatT) -> Vec3 {
+    /// Creates a vector from its three components.
+    pub const fn new(e0: FloatT, e1: FloatT, e2: FloatT) -> Vec3 {
         Vec3 {
             e: [e0, e1, e2]
         }
     }
 
+    /// First component.
     pub fn x(self) -> FloatT {
         self[0]
     }
 
+    /// Second component.
     pub fn y(self) -> FloatT {
         self[1]
     }
 
+    /// Third component.
     pub fn z(self) -> FloatT {
         self[2]
     }
 
+    /// Dot product.
     pub fn dot(self, other: Vec3) -> FloatT {
-        return self.x() * other.x() + self.y() * other.y() + self.z() * other.z()
+        self.x() * other.x() + self.y() * other.y() + self.z() * other.z()
     }
 
+    /// Euclidean length.
     pub fn length(self) -> FloatT {
-        return self.dot(self).sqrt()
+        self.dot(self).sqrt()
     }
 
+    /// Cross product.
     pub fn cross(self, other: Vec3) -> Vec3 {
         Vec3 {
             e: [
@@ -51,15 +62,18 @@ impl Vec3 {
         }
     }
 
+    /// Unit vector in the same direction.
     pub fn normalized(self) -> Vec3 {
         self / self.length()
     }
 
+    /// Mirror reflection about the normal `n`.
     pub fn reflect(self, n: Vec3) -> Vec3 {
         self - 2.0 * self.dot(n) * n
     }
     
     // 𝜃′= sin^-1(𝜂/𝜂′⋅sin𝜃)
+    /// Refracts a unit vector through a surface with normal `n` using Snell's law.
     pub fn refract(self, n: Vec3, eta_over_etap: FloatT) -> Vec3 {
         let cos_theta = ((-1.0) * self).dot(n).min(1.0);
 
@@ -69,24 +83,35 @@ impl Vec3 {
         r_out_perp + r_out_parallel
     }
 
-    pub fn random(r: Range<FloatT>) -> Vec3 {
-        let mut rng = thread_rng();
+    /// Two unit vectors that form an orthonormal basis with this unit vector, after Duff et al.
+    pub fn basis(self) -> (Vec3, Vec3) {
+        let sign = (1.0 as FloatT).copysign(self.z());
+        let a = -1.0 / (sign + self.z());
+        let b = self.x() * self.y() * a;
+        (Vec3::new(1.0 + sign * self.x() * self.x() * a, sign * b, -sign * self.x()),
+         Vec3::new(b, sign + self.y() * self.y() * a, -self.y()))
+    }
+
+    /// Vector with each component drawn uniformly from `r`.
+    pub fn random<R: Rng + ?Sized>(r: Range<FloatT>, rng: &mut R) -> Vec3 {
         Vec3 {
             e: [rng.gen_range(r.clone()), rng.gen_range(r.clone()), rng.gen_range(r.clone())]
         }
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
+    /// Uniformly distributed point inside the unit sphere.
+    pub fn random_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         loop {
-            let v = Vec3::random(-1.0..1.0);
+            let v = Vec3::random(-1.0..1.0, rng);
             if v.length() < 1.0 {
                 return v;
             }
         }
     }
 
-    pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-        let in_unit_sphere = Self::random_in_unit_sphere();
+    /// Point inside the unit sphere on the same side as `normal`.
+    pub fn random_in_hemisphere<R: Rng + ?Sized>(normal: Vec3, rng: &mut R) -> Vec3 {
+        let in_unit_sphere = Self::random_in_unit_sphere(rng);
 
         // In the same hemisphere as normal
         if in_unit_sphere.dot(normal) > 0.0 {
@@ -96,9 +121,8 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disc() -> Vec3 {
-        let mut rng = rand::thread_rng();
-
+    /// Uniformly distributed point inside the unit disc in the xy plane.
+    pub fn random_in_unit_disc<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         loop {
             let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
             if p.length() < 1.0 {
@@ -107,18 +131,16 @@ impl Vec3 {
         }
     }
 
+    /// Rec. 709 luminance, treating the vector as a linear color.
+    pub fn luminance(self) -> FloatT {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
+
+    /// True if every component is very close to zero.
     pub fn near_zero(self) -> bool {
         const EPS: FloatT = 1.0e-8;
         self.x().abs() < EPS && self.y().abs() < EPS && self.z().abs() < EPS
     }
-    
-    pub fn format_color(self, samples_per_pixel: u64) -> String {
-        let ir = (256.0 * (self.x() / (samples_per_pixel as FloatT)).sqrt().clamp(0.0, 0.999)) as u64;
-        let ig = (256.0 * (self.y() / (samples_per_pixel as FloatT)).sqrt().clamp(0.0, 0.999)) as u64;
-        let ib = (256.0 * (self.z() / (samples_per_pixel as FloatT)).sqrt().clamp(0.0, 0.999)) as u64;
-
-        format!("{} {} {}", ir, ig, ib)
-    }
 }
 
 impl Index<usize> for Vec3 {
@@ -146,7 +168,7 @@ impl Add for Vec3 {
 }
 
 impl AddAssign for Vec3 {
-    fn add_assign(&mut self, other: Vec3) -> () {
+    fn add_assign(&mut self, other: Vec3) {
         *self = Vec3 {
             e: [self[0] + other[0], self[1] + other[1], self[2] + other[2]]
         };
@@ -173,7 +195,7 @@ impl Sub<FloatT> for Vec3 {
 }
 
 impl SubAssign for Vec3 {
-    fn sub_assign(&mut self, other: Vec3) -> () {
+    fn sub_assign(&mut self, other: Vec3) {
         *self = Vec3 {
             e: [self[0] - other[0], self[1] - other[1], self[2] - other[2]]
         };
@@ -191,7 +213,7 @@ impl Mul<FloatT> for Vec3 {
 }
 
 impl MulAssign<FloatT> for Vec3 {
-    fn mul_assign(&mut self, other: FloatT) -> () {
+    fn mul_assign(&mut self, other: FloatT) {
         *self = Vec3 {
             e: [self[0] * other, self[1] * other, self[2] * other]
         };
@@ -239,7 +261,7 @@ impl Div<FloatT> for Vec3 {
 }
 
 impl DivAssign<FloatT> for Vec3 {
-    fn div_assign(&mut self, other: FloatT) -> () {
+    fn div_assign(&mut self, other: FloatT) {
         *self = Vec3 {
             e: [self[0] / other, self[1] / other, self[2] / other]
         };